@@ -1,4 +1,5 @@
 pub mod google {
+    use super::commentary::{CommentaryRequest, HostCommentary};
     use serde_derive::Deserialize;
     use serde_derive::Serialize;
     use serde_json::json;
@@ -69,14 +70,27 @@ pub mod google {
         pub token_count: i64,
     }
 
-    pub fn prompt_gemini(prompt: String) -> Result<String, String> {
-        let api_key = match std::env::var("GOOGLE_GENAI_STUDIO_API_KEY") {
-            Ok(api_key) => api_key,
-            Err(_) => {
-                panic!("Unable to get Google GenAI Studio API key. Use an environment variable called GOOGLE_GENAI_STUDIO_API_KEY to set it.");
-            }
-        };
+    pub struct GeminiCommentary {
+        api_key: String,
+    }
+
+    impl GeminiCommentary {
+        pub fn new(api_key: String) -> GeminiCommentary {
+            GeminiCommentary { api_key }
+        }
+    }
+
+    impl HostCommentary for GeminiCommentary {
+        fn name(&self) -> &str {
+            "gemini"
+        }
+
+        fn comment(&self, request: &CommentaryRequest) -> Result<String, String> {
+            prompt_gemini(&self.api_key, request.prompt())
+        }
+    }
 
+    pub fn prompt_gemini(api_key: &str, prompt: String) -> Result<String, String> {
         let body = json!({
             "contents": [
                 {
@@ -120,161 +134,1074 @@ pub mod google {
             }
         };
 
-        return Ok(response_json.candidates[0].content.parts[0]
-            .text
-            .as_str()
-            .trim_end()
-            .to_string());
+        match response_json
+            .candidates
+            .first()
+            .and_then(|candidate| candidate.content.parts.first())
+        {
+            Some(part) => Ok(part.text.trim_end().to_string()),
+            None => Err("Gemini returned no text".to_string()),
+        }
     }
 }
 
-pub mod elevenlabs {
-    use crate::helpers::time_helpers::uptime_ms;
+pub mod openai {
+    use super::commentary::{CommentaryRequest, HostCommentary};
     use serde_json::json;
-    use std::{fs::File, io::Write, path::Path};
-
-    const ELEVENLABS_API_ENDPOINT: &str = "https://api.elevenlabs.io/v1/text-to-speech/";
-    const ELEVENLABS_VOICE_ID: &str = "YSabzCJMvEHDduIDMdwV"; // Aurora
-    const ELEVENLABS_MODEL_ID: &str = "eleven_flash_v2_5";
 
-    pub enum AudioType {
-        Question,
-        Answer,
-        NoCache,
+    /// Any server speaking OpenAI chat completions API, such as a local llama.cpp server or
+    /// Ollama
+    pub struct OpenAiCommentary {
+        /// Base URL up to and including `/v1`
+        pub endpoint: String,
+        pub model: String,
+        pub api_key: Option<String>,
     }
 
-    fn check_if_audio_exists(question_id: &i64, audio_type: &AudioType) -> Option<String> {
-        //let mut filename = "".to_string();
-        let filename = match audio_type {
-            AudioType::Question => format!("audio/q-{}.mp3", question_id.to_string()),
-            AudioType::Answer => format!("audio/a-{}.mp3", question_id.to_string()),
-            AudioType::NoCache => {
-                return None;
+    impl HostCommentary for OpenAiCommentary {
+        fn name(&self) -> &str {
+            "openai"
+        }
+
+        fn comment(&self, request: &CommentaryRequest) -> Result<String, String> {
+            let body = json!({
+                "model": self.model,
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": request.prompt()
+                    }
+                ],
+                "temperature": 1,
+                "max_tokens": 1024
+            })
+            .to_string();
+
+            let url = format!("{}/chat/completions", self.endpoint.trim_end_matches('/'));
+            let mut http_request = ureq::post(url).header("Content-Type", "application/json");
+            if let Some(api_key) = &self.api_key {
+                http_request = http_request.header("Authorization", format!("Bearer {}", api_key));
+            }
+            let mut response = match http_request.send(body) {
+                Ok(response) => response,
+                Err(error) => {
+                    return Err(format!("{}", error));
+                }
+            };
+
+            let response_string = match response.body_mut().read_to_string() {
+                Ok(response_string) => response_string,
+                Err(error) => {
+                    return Err(format!("{}", error));
+                }
+            };
+
+            let response_json: serde_json::Value = match serde_json::from_str(&response_string) {
+                Ok(json) => json,
+                Err(error) => {
+                    return Err(format!("{}", error));
+                }
+            };
+
+            match response_json["choices"][0]["message"]["content"].as_str() {
+                Some(text) if !text.trim().is_empty() => Ok(text.trim().to_string()),
+                _ => Err(format!("{} returned no text", self.model)),
             }
-        };
-        let final_filename = format!("web/{}", &filename);
-        if Path::new(&final_filename).exists() {
-            return Some(filename);
-        } else {
-            return None;
         }
     }
+}
+
+pub mod commentary {
+    use crate::helpers::natural_language::{
+        get_player_names_for_tts, prompt_for_player_introduction,
+        prompt_for_shared_team_win_announcement, prompt_for_shared_win_announcement,
+        prompt_for_team_winner_announcement, prompt_for_winner_announcement,
+        template_for_player_introduction, template_for_shared_team_win_announcement,
+        template_for_shared_win_announcement, template_for_team_winner_announcement,
+        template_for_winner_announcement,
+    };
+    use std::sync::Arc;
 
-    pub fn generate_speech(
-        text: &String,
-        question_id: &i64,
-        audio_type: AudioType,
-    ) -> Result<String, String> {
-        //generate_speech_dummy(text, question_id, audio_type)
-        generate_speech_elevenlabs(text, question_id, audio_type)
+    /// Something the host says in their own words
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum CommentaryRequest {
+        PlayerIntroduction {
+            players: Vec<String>,
+        },
+        WinnerAnnouncement {
+            winner: String,
+            points: u32,
+        },
+        SharedWinAnnouncement {
+            winners: Vec<String>,
+            points: u32,
+        },
+        TeamWinnerAnnouncement {
+            team: String,
+            members: Vec<String>,
+            points: u32,
+        },
+        SharedTeamWinAnnouncement {
+            teams: Vec<String>,
+            points: u32,
+        },
     }
 
-    fn generate_speech_dummy(
-        text: &String,
-        question_id: &i64,
-        audio_type: AudioType,
-    ) -> Result<String, String> {
-        println!("Generate speech (dummy): {}", text);
-        match check_if_audio_exists(question_id, &audio_type) {
-            Some(filename) => {
-                println!("Speech is cached, returning {}", filename);
-                return Ok(filename);
+    impl CommentaryRequest {
+        /// Prompt for language models
+        pub fn prompt(&self) -> String {
+            match self {
+                CommentaryRequest::PlayerIntroduction { players } => {
+                    prompt_for_player_introduction(get_player_names_for_tts(players.clone()))
+                }
+                CommentaryRequest::WinnerAnnouncement { winner, points } => {
+                    prompt_for_winner_announcement(winner.clone(), points.to_string())
+                }
+                CommentaryRequest::SharedWinAnnouncement { winners, points } => {
+                    prompt_for_shared_win_announcement(
+                        get_player_names_for_tts(winners.clone()),
+                        points.to_string(),
+                    )
+                }
+                CommentaryRequest::TeamWinnerAnnouncement {
+                    team,
+                    members,
+                    points,
+                } => prompt_for_team_winner_announcement(
+                    team.clone(),
+                    get_player_names_for_tts(members.clone()),
+                    points.to_string(),
+                ),
+                CommentaryRequest::SharedTeamWinAnnouncement { teams, points } => {
+                    prompt_for_shared_team_win_announcement(
+                        get_player_names_for_tts(teams.clone()),
+                        points.to_string(),
+                    )
+                }
             }
-            None => {
-                println!("Not cached");
+        }
+
+        /// Ready-made text which needs no language model
+        pub fn template(&self) -> String {
+            match self {
+                CommentaryRequest::PlayerIntroduction { players } => {
+                    template_for_player_introduction(get_player_names_for_tts(players.clone()))
+                }
+                CommentaryRequest::WinnerAnnouncement { winner, points } => {
+                    template_for_winner_announcement(winner.clone(), points.to_string())
+                }
+                CommentaryRequest::SharedWinAnnouncement { winners, points } => {
+                    template_for_shared_win_announcement(
+                        get_player_names_for_tts(winners.clone()),
+                        points.to_string(),
+                    )
+                }
+                CommentaryRequest::TeamWinnerAnnouncement {
+                    team,
+                    members,
+                    points,
+                } => template_for_team_winner_announcement(
+                    team.clone(),
+                    get_player_names_for_tts(members.clone()),
+                    points.to_string(),
+                ),
+                CommentaryRequest::SharedTeamWinAnnouncement { teams, points } => {
+                    template_for_shared_team_win_announcement(
+                        get_player_names_for_tts(teams.clone()),
+                        points.to_string(),
+                    )
+                }
             }
+        }
+    }
+
+    /// Writes what the host says between questions
+    pub trait HostCommentary: Send + Sync {
+        fn name(&self) -> &str;
+        fn comment(&self, request: &CommentaryRequest) -> Result<String, String>;
+    }
+
+    /// Works offline, also used whenever another backend fails
+    pub struct TemplateCommentary;
+
+    impl HostCommentary for TemplateCommentary {
+        fn name(&self) -> &str {
+            "template"
+        }
+
+        fn comment(&self, request: &CommentaryRequest) -> Result<String, String> {
+            Ok(request.template())
+        }
+    }
+
+    /// Commentary from the chosen backend, or from templates if it fails
+    pub fn commentate(commentary: &dyn HostCommentary, request: &CommentaryRequest) -> String {
+        match commentary.comment(request) {
+            Ok(text) => text,
+            Err(error) => {
+                eprintln!(
+                    "Host commentary ({}) failed, using a template instead: {}",
+                    commentary.name(),
+                    error
+                );
+                request.template()
+            }
+        }
+    }
+
+    /// Backend chosen with PUBIQ_LLM (`gemini`, `openai` or `template`). OpenAI compatible
+    /// server is given with PUBIQ_LLM_URL and PUBIQ_LLM_MODEL, and PUBIQ_LLM_API_KEY if it
+    /// needs one. Without PUBIQ_LLM Gemini is used if its API key is set.
+    pub fn commentary_from_env() -> Arc<dyn HostCommentary> {
+        let backend = match std::env::var("PUBIQ_LLM") {
+            Ok(backend) => backend.to_lowercase(),
+            Err(_) => match std::env::var("GOOGLE_GENAI_STUDIO_API_KEY") {
+                Ok(_) => "gemini".to_string(),
+                Err(_) => "template".to_string(),
+            },
         };
-        let filename = format!("audio/{}.mp3", uptime_ms().to_string());
-        let source_file = match &audio_type {
-            AudioType::Answer => "blip.mp3".to_string(),
-            AudioType::Question => "blip.mp3".to_string(),
-            AudioType::NoCache => "blip.mp3".to_string(),
+
+        let commentary: Result<Arc<dyn HostCommentary>, String> = match backend.as_str() {
+            "gemini" => match std::env::var("GOOGLE_GENAI_STUDIO_API_KEY") {
+                Ok(api_key) => Ok(Arc::new(super::google::GeminiCommentary::new(api_key))),
+                Err(_) => Err("Use an environment variable called GOOGLE_GENAI_STUDIO_API_KEY to set Google GenAI Studio API key".to_string()),
+            },
+            "openai" => Ok(Arc::new(super::openai::OpenAiCommentary {
+                endpoint: std::env::var("PUBIQ_LLM_URL")
+                    .unwrap_or("http://localhost:11434/v1".to_string()),
+                model: std::env::var("PUBIQ_LLM_MODEL").unwrap_or("llama3.2".to_string()),
+                api_key: std::env::var("PUBIQ_LLM_API_KEY").ok(),
+            })),
+            "template" => Ok(Arc::new(TemplateCommentary)),
+            other => Err(format!("Unknown host commentary backend {}", other)),
         };
-        match std::fs::copy(
-            format!("web/audio/{}", source_file),
-            format!("web/{}", &filename),
-        ) {
-            Ok(_) => {
-                println!("Filename: {}", filename);
-                return Ok(filename);
+
+        match commentary {
+            Ok(commentary) => {
+                println!("Host commentary: {}", commentary.name());
+                commentary
             }
             Err(error) => {
-                println!("Failed: {}", error);
-                return Err("".to_string());
+                eprintln!("{}. Using templates.", error);
+                Arc::new(TemplateCommentary)
             }
         }
     }
+}
 
-    fn generate_speech_elevenlabs(
-        text: &String,
-        question_id: &i64,
-        audio_type: AudioType,
-    ) -> Result<String, String> {
-        let api_key = match std::env::var("ELEVENLABS_API_KEY") {
-            Ok(api_key) => api_key,
-            Err(_) => {
-                panic!("Unable to get Elevenlabs API key. Use an environment variable called ELEVENLABS_API_KEY to set it. If you don't have an API key, use generate_speech_dummy function instead.");
+pub mod host {
+    use super::{
+        commentary::{commentary_from_env, HostCommentary},
+        speech::{synthesizer_from_env, SpeechSynthesizer},
+        speech_cache::{AudioType, SpeechCache},
+    };
+    use crate::questions::structure::QuestionId;
+    use std::sync::Arc;
+
+    /// Words and voice of the quiz host, shared by every room
+    #[derive(Clone)]
+    pub struct Host {
+        pub speech: Arc<dyn SpeechSynthesizer>,
+        pub speech_cache: Arc<SpeechCache>,
+        pub commentary: Arc<dyn HostCommentary>,
+    }
+
+    impl Host {
+        pub fn from_env() -> Host {
+            Host {
+                speech: synthesizer_from_env(),
+                speech_cache: Arc::new(SpeechCache::open("web")),
+                commentary: commentary_from_env(),
             }
-        };
+        }
+
+        /// Audio of the text from cache or speech engine, see SpeechCache::speak
+        pub fn speak(
+            &self,
+            text: &str,
+            question_id: &QuestionId,
+            audio_type: AudioType,
+        ) -> Option<String> {
+            self.speech_cache
+                .speak(self.speech.as_ref(), text, question_id, audio_type)
+        }
+
+        /// One-off audio of the text for the room, see SpeechCache::announce
+        pub fn announce(&self, text: &str, room_code: &str) -> Option<String> {
+            self.speech_cache
+                .announce(self.speech.as_ref(), text, room_code)
+        }
+    }
+}
+
+pub mod speech {
+    use std::{
+        io::Write,
+        process::{Command, Stdio},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+    };
+
+    /// Keeps output files of simultaneous synthesizer runs apart
+    static OUTPUT_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
 
-        println!("Generate speech: {}", text);
-        match check_if_audio_exists(&question_id, &audio_type) {
-            Some(filename) => {
-                println!("Speech is cached, returning {}", filename);
-                return Ok(filename);
+    /// Turns text into audio the presenter plays
+    pub trait SpeechSynthesizer: Send + Sync {
+        /// Short name, part of the cache key so that engines don't share audio
+        fn name(&self) -> &str;
+        /// Everything besides text which changes how the audio sounds, such as voice, model
+        /// and voice settings. Part of the cache key.
+        fn voice(&self) -> String;
+        fn extension(&self) -> &str;
+        /// Audio file contents, or None if this engine doesn't produce audio at all
+        fn synthesize(&self, text: &str) -> Result<Option<Vec<u8>>, String>;
+    }
+
+    /// No audio, presenter moves on after a while instead of waiting for the narrator
+    pub struct SilentSynthesizer;
+
+    impl SpeechSynthesizer for SilentSynthesizer {
+        fn name(&self) -> &str {
+            "silent"
+        }
+
+        fn voice(&self) -> String {
+            "".to_string()
+        }
+
+        fn extension(&self) -> &str {
+            "mp3"
+        }
+
+        fn synthesize(&self, _text: &str) -> Result<Option<Vec<u8>>, String> {
+            Ok(None)
+        }
+    }
+
+    /// Local speech engine run as a subprocess, so it works offline. Text is written to
+    /// its stdin. Audio is read from stdout, or from a temporary file if an argument is
+    /// OUTPUT_PLACEHOLDER.
+    pub struct CommandSynthesizer {
+        pub name: String,
+        pub command: String,
+        pub args: Vec<String>,
+        pub extension: String,
+    }
+
+    const OUTPUT_PLACEHOLDER: &str = "{output}";
+
+    impl CommandSynthesizer {
+        /// Piper neural voices, `model` is path to a voice such as fi_FI-harri-medium.onnx
+        pub fn piper(model: &str) -> CommandSynthesizer {
+            CommandSynthesizer {
+                name: "piper".to_string(),
+                command: "piper".to_string(),
+                args: vec![
+                    "--model".to_string(),
+                    model.to_string(),
+                    "--output_file".to_string(),
+                    OUTPUT_PLACEHOLDER.to_string(),
+                ],
+                extension: "wav".to_string(),
             }
-            None => {}
-        };
-        let body = json!({
-            "text": text,
-            "model_id": ELEVENLABS_MODEL_ID,
-            "voice_settings": {
-                "stability": 0.51,
-                "similarity_boost": 0.75,
-                "speed": 0.89
+        }
+
+        pub fn espeak(voice: &str) -> CommandSynthesizer {
+            CommandSynthesizer {
+                name: "espeak".to_string(),
+                command: "espeak-ng".to_string(),
+                args: vec![
+                    "-v".to_string(),
+                    voice.to_string(),
+                    "--stdin".to_string(),
+                    "--stdout".to_string(),
+                ],
+                extension: "wav".to_string(),
             }
-        })
-        .to_string();
+        }
+    }
 
-        let mut response = match ureq::post(format!(
-            "{}{}",
-            ELEVENLABS_API_ENDPOINT, ELEVENLABS_VOICE_ID
-        ))
-        .header("xi-api-key", api_key)
-        .header("Content-Type", "application/json")
-        .send(body)
-        {
-            Ok(response) => response,
-            Err(error) => {
-                return Err(format!("{}", error));
+    impl SpeechSynthesizer for CommandSynthesizer {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn voice(&self) -> String {
+            format!("{} {}", self.command, self.args.join(" "))
+        }
+
+        fn extension(&self) -> &str {
+            &self.extension
+        }
+
+        fn synthesize(&self, text: &str) -> Result<Option<Vec<u8>>, String> {
+            let output_file = std::env::temp_dir().join(format!(
+                "pubiq-{}-{}-{}.{}",
+                self.name,
+                std::process::id(),
+                OUTPUT_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+                self.extension
+            ));
+            let output_path = output_file.display().to_string();
+            let uses_file = self.args.iter().any(|arg| arg == OUTPUT_PLACEHOLDER);
+            let args: Vec<String> = self
+                .args
+                .iter()
+                .map(|arg| arg.replace(OUTPUT_PLACEHOLDER, &output_path))
+                .collect();
+
+            let mut child = match Command::new(&self.command)
+                .args(&args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+            {
+                Ok(child) => child,
+                Err(error) => return Err(format!("Unable to run {}: {}", self.command, error)),
+            };
+            if let Some(mut stdin) = child.stdin.take() {
+                if let Err(error) = stdin.write_all(text.as_bytes()) {
+                    // Don't leave the process running or as a zombie
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    let _ = std::fs::remove_file(&output_file);
+                    return Err(format!(
+                        "Unable to send text to {}: {}",
+                        self.command, error
+                    ));
+                }
+            }
+            let output = match child.wait_with_output() {
+                Ok(output) => output,
+                Err(error) => {
+                    let _ = std::fs::remove_file(&output_file);
+                    return Err(format!("{} failed: {}", self.command, error));
+                }
+            };
+            if !output.status.success() {
+                let _ = std::fs::remove_file(&output_file);
+                return Err(format!(
+                    "{} failed ({}): {}",
+                    self.command,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
             }
-        };
 
-        let body = match response.body_mut().read_to_vec() {
-            Ok(vec) => vec,
-            Err(error) => {
-                return Err(format!("{}", error));
+            let audio = match uses_file {
+                true => {
+                    let audio = std::fs::read(&output_file);
+                    let _ = std::fs::remove_file(&output_file);
+                    match audio {
+                        Ok(audio) => audio,
+                        Err(error) => {
+                            return Err(format!("{} wrote no audio: {}", self.command, error))
+                        }
+                    }
+                }
+                false => output.stdout,
+            };
+            match audio.is_empty() {
+                true => Err(format!("{} produced no audio", self.command)),
+                false => Ok(Some(audio)),
             }
+        }
+    }
+
+    /// Engine chosen with PUBIQ_TTS (`elevenlabs`, `piper`, `espeak` or `silent`). Piper
+    /// needs a voice model in PUBIQ_TTS_VOICE, for espeak it is a voice name (default
+    /// `fi`). Without PUBIQ_TTS ElevenLabs is used if its API key is set. An engine that
+    /// can't be used falls back to silence.
+    pub fn synthesizer_from_env() -> Arc<dyn SpeechSynthesizer> {
+        let voice = std::env::var("PUBIQ_TTS_VOICE").ok();
+        let engine = match std::env::var("PUBIQ_TTS") {
+            Ok(engine) => engine.to_lowercase(),
+            Err(_) => match std::env::var("ELEVENLABS_API_KEY") {
+                Ok(_) => "elevenlabs".to_string(),
+                Err(_) => "silent".to_string(),
+            },
         };
 
-        let filename: String = match audio_type {
-            AudioType::Answer => format!("audio/a-{}.mp3", question_id).to_string(),
-            AudioType::Question => format!("audio/q-{}.mp3", question_id).to_string(),
-            AudioType::NoCache => format!("audio/nocache-{}.mp3", uptime_ms()).to_string(),
+        let synthesizer: Result<Arc<dyn SpeechSynthesizer>, String> = match engine.as_str() {
+            "elevenlabs" => match std::env::var("ELEVENLABS_API_KEY") {
+                Ok(api_key) => Ok(Arc::new(super::elevenlabs::ElevenLabsSynthesizer::new(
+                    api_key,
+                ))),
+                Err(_) => Err("Use an environment variable called ELEVENLABS_API_KEY to set ElevenLabs API key".to_string()),
+            },
+            "piper" => match voice {
+                Some(model) => Ok(Arc::new(CommandSynthesizer::piper(&model))),
+                None => Err("Piper needs a voice model in PUBIQ_TTS_VOICE".to_string()),
+            },
+            "espeak" | "espeak-ng" => Ok(Arc::new(CommandSynthesizer::espeak(
+                voice.as_deref().unwrap_or("fi"),
+            ))),
+            "silent" => Ok(Arc::new(SilentSynthesizer)),
+            other => Err(format!("Unknown speech engine {}", other)),
         };
 
-        let mut file = match File::create(format!("web/{}", &filename)) {
-            Ok(file) => file,
+        match synthesizer {
+            Ok(synthesizer) => {
+                println!("Speech engine: {}", synthesizer.name());
+                synthesizer
+            }
             Err(error) => {
-                return Err(format!("{}", error));
+                eprintln!("{}. Continuing without speech.", error);
+                Arc::new(SilentSynthesizer)
             }
+        }
+    }
+}
+
+pub mod speech_cache {
+    use super::speech::SpeechSynthesizer;
+    use crate::helpers::time_helpers::{unix_time_s, uptime_ms};
+    use crate::questions::structure::QuestionId;
+    use serde::{Deserialize, Serialize};
+    use std::{
+        collections::{BTreeMap, HashSet},
+        fs::File,
+        io::Write,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Condvar, Mutex,
+        },
+    };
+
+    /// Cached audio is kept here, relative to web directory
+    pub const SPEECH_CACHE_DIR: &str = "audio/tts";
+    const MANIFEST_FILE: &str = "manifest.json";
+    /// How many one-off announcements are kept, older ones are deleted
+    const NOCACHE_KEEP: usize = 20;
+    /// Tells apart announcements made during the same millisecond
+    static NOCACHE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum AudioType {
+        Question,
+        Answer,
+        /// One-off announcement, each gets its own file and only the latest few are kept
+        NoCache,
+    }
+
+    /// What a cached audio file contains and how it was made
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CacheEntry {
+        pub file: String,
+        pub audio_type: AudioType,
+        /// Question the audio belongs to when it was made, not used for lookups
+        #[serde(default)]
+        pub question: Option<String>,
+        pub engine: String,
+        pub voice: String,
+        pub text: String,
+        pub created_at: u64,
+    }
+
+    /// Cache entries by key, saved next to the audio files
+    #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+    pub struct Manifest {
+        pub entries: BTreeMap<String, CacheEntry>,
+    }
+
+    /// Speech audio stored by a hash of engine, voice and text, so that changing any of them
+    /// produces new audio instead of playing the old one
+    pub struct SpeechCache {
+        dir: PathBuf,
+        manifest: Mutex<Manifest>,
+        /// Keys being synthesized right now, others asking for them wait for the result
+        generating: Mutex<HashSet<String>>,
+        generated: Condvar,
+    }
+
+    /// Cache key for text spoken by the engine with its current voice
+    pub fn cache_key(speech: &dyn SpeechSynthesizer, text: &str) -> String {
+        let key = format!("{}\n{}\n{}", speech.name(), speech.voice(), text);
+        sha1_smol::Sha1::from(key.as_bytes()).digest().to_string()
+    }
+
+    /// Generated audio which the first version saved straight into the audio directory:
+    /// `q-<id>.mp3`, `a-<id>.mp3` and `nocache-<time>.mp3`
+    pub fn is_legacy_audio(name: &str) -> bool {
+        let stem = match name.strip_suffix(".mp3") {
+            Some(stem) => stem,
+            None => return false,
         };
-        match file.write_all(&body) {
-            Ok(_) => {
-                return Ok(filename);
+        ["q-", "a-", "nocache-"].iter().any(|prefix| {
+            stem.strip_prefix(prefix).is_some_and(|number| {
+                !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+            })
+        })
+    }
+
+    impl SpeechCache {
+        /// Cache in the given directory of web root, empty if there is no manifest yet
+        pub fn open(web_dir: &str) -> SpeechCache {
+            let dir = Path::new(web_dir).join(SPEECH_CACHE_DIR);
+            if let Err(error) = std::fs::create_dir_all(&dir) {
+                eprintln!("Unable to create {}: {}", dir.display(), error);
             }
-            Err(error) => {
-                return Err(format!("{}", error));
+            let manifest = match std::fs::read_to_string(dir.join(MANIFEST_FILE)) {
+                Ok(data) => match serde_json::from_str(&data) {
+                    Ok(manifest) => manifest,
+                    Err(error) => {
+                        eprintln!("Speech cache manifest is broken, starting fresh: {}", error);
+                        Manifest::default()
+                    }
+                },
+                Err(_) => Manifest::default(),
+            };
+            SpeechCache {
+                dir,
+                manifest: Mutex::new(manifest),
+                generating: Mutex::new(HashSet::new()),
+                generated: Condvar::new(),
+            }
+        }
+
+        /// Another process, such as `pubiq warm-cache` next to a running server, may have
+        /// saved audio since the manifest was read. Its entries are kept when saving.
+        fn merge_saved_manifest(&self, manifest: &mut Manifest) {
+            let saved: Manifest = match std::fs::read_to_string(self.dir.join(MANIFEST_FILE))
+                .ok()
+                .and_then(|data| serde_json::from_str(&data).ok())
+            {
+                Some(saved) => saved,
+                None => return,
+            };
+            for (key, entry) in saved.entries {
+                if !manifest.entries.contains_key(&key) && self.dir.join(&entry.file).exists() {
+                    manifest.entries.insert(key, entry);
+                }
+            }
+        }
+
+        fn save_manifest(&self, manifest: &mut Manifest) {
+            self.merge_saved_manifest(manifest);
+            let data = match serde_json::to_string_pretty(manifest) {
+                Ok(data) => data,
+                Err(error) => {
+                    eprintln!("Unable to save speech cache manifest: {}", error);
+                    return;
+                }
+            };
+            // Written aside and renamed so that a crash never leaves half a manifest
+            let temporary = self.dir.join(format!("{}.tmp", MANIFEST_FILE));
+            let result = std::fs::write(&temporary, data)
+                .and_then(|_| std::fs::rename(&temporary, self.dir.join(MANIFEST_FILE)));
+            if let Err(error) = result {
+                eprintln!("Unable to save speech cache manifest: {}", error);
+            }
+        }
+
+        /// Audio file for the text relative to web directory, None if there is no audio.
+        /// Failures are logged and the game goes on without audio.
+        pub fn speak(
+            &self,
+            speech: &dyn SpeechSynthesizer,
+            text: &str,
+            question_id: &QuestionId,
+            audio_type: AudioType,
+        ) -> Option<String> {
+            if audio_type == AudioType::NoCache {
+                return self.announce(speech, text, "");
+            }
+            let key = cache_key(speech, text);
+            if let Some(file) = self.cached_file(&key) {
+                return Some(file);
+            }
+            // Audio preparation and live narration may ask for the same audio at once
+            if !self.claim(&key) {
+                return self.cached_file(&key);
+            }
+            let audio = match self.cached_file(&key) {
+                Some(file) => Some(file),
+                None => self.generate(speech, text, key.clone(), question_id, audio_type),
+            };
+            self.release(&key);
+            audio
+        }
+
+        fn cached_file(&self, key: &str) -> Option<String> {
+            let manifest_mutex = match self.manifest.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+            let entry = manifest_mutex.entries.get(key)?;
+            if !self.dir.join(&entry.file).exists() {
+                return None;
+            }
+            println!("Speech is cached, returning {}", entry.file);
+            Some(format!("{}/{}", SPEECH_CACHE_DIR, entry.file))
+        }
+
+        /// Reserve the key for generating its audio. If somebody else is already generating
+        /// it, waits until they are done and returns false.
+        fn claim(&self, key: &str) -> bool {
+            let mut generating_mutex = match self.generating.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+            if generating_mutex.insert(key.to_string()) {
+                return true;
+            }
+            while generating_mutex.contains(key) {
+                generating_mutex = match self.generated.wait(generating_mutex) {
+                    Ok(mutex) => mutex,
+                    Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+                };
+            }
+            false
+        }
+
+        fn release(&self, key: &str) {
+            let mut generating_mutex = match self.generating.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+            generating_mutex.remove(key);
+            self.generated.notify_all();
+        }
+
+        /// Audio file of a one-off announcement in the room, never taken from cache. The file
+        /// name has the room code and a counter so that rooms speaking at the same moment
+        /// don't overwrite each other's audio.
+        pub fn announce(
+            &self,
+            speech: &dyn SpeechSynthesizer,
+            text: &str,
+            room_code: &str,
+        ) -> Option<String> {
+            let key = format!(
+                "nocache-{}-{}-{}",
+                room_code,
+                uptime_ms(),
+                NOCACHE_COUNTER.fetch_add(1, Ordering::Relaxed)
+            );
+            self.claim(&key);
+            let audio = self.generate(
+                speech,
+                text,
+                key.clone(),
+                &QuestionId::default(),
+                AudioType::NoCache,
+            );
+            self.release(&key);
+            audio
+        }
+
+        /// Synthesize the text to a file named by the key and add it to the manifest
+        fn generate(
+            &self,
+            speech: &dyn SpeechSynthesizer,
+            text: &str,
+            key: String,
+            question_id: &QuestionId,
+            audio_type: AudioType,
+        ) -> Option<String> {
+            println!("Generate speech ({}): {}", speech.name(), text);
+            let audio = match speech.synthesize(text) {
+                Ok(Some(audio)) => audio,
+                Ok(None) => return None,
+                Err(error) => {
+                    eprintln!("Unable to generate speech: {}", error);
+                    return None;
+                }
+            };
+
+            // Written aside and renamed so that nobody gets a half written file
+            let filename = format!("{}.{}", key, speech.extension());
+            let temporary = self.dir.join(format!("{}.tmp", filename));
+            let result = File::create(&temporary)
+                .and_then(|mut file| file.write_all(&audio))
+                .and_then(|_| std::fs::rename(&temporary, self.dir.join(&filename)));
+            if let Err(error) = result {
+                let _ = std::fs::remove_file(&temporary);
+                eprintln!("Unable to save speech to {}: {}", filename, error);
+                return None;
+            }
+
+            let mut manifest_mutex = match self.manifest.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+            manifest_mutex.entries.insert(
+                key,
+                CacheEntry {
+                    file: filename.clone(),
+                    audio_type,
+                    question: match audio_type {
+                        AudioType::NoCache => None,
+                        _ => Some(question_id.to_string()),
+                    },
+                    engine: speech.name().to_string(),
+                    voice: speech.voice(),
+                    text: text.to_string(),
+                    created_at: unix_time_s(),
+                },
+            );
+            if audio_type == AudioType::NoCache {
+                self.evict_nocache(&mut manifest_mutex);
+            }
+            self.save_manifest(&mut manifest_mutex);
+            Some(format!("{}/{}", SPEECH_CACHE_DIR, filename))
+        }
+
+        /// Delete all but the latest one-off announcements
+        fn evict_nocache(&self, manifest: &mut Manifest) {
+            let mut nocache: Vec<(u64, String)> = manifest
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.audio_type == AudioType::NoCache)
+                .map(|(key, entry)| (entry.created_at, key.clone()))
+                .collect();
+            nocache.sort();
+            let evicted = nocache.len().saturating_sub(NOCACHE_KEEP);
+            for (_, key) in nocache.into_iter().take(evicted) {
+                if let Some(entry) = manifest.entries.remove(&key) {
+                    let _ = std::fs::remove_file(self.dir.join(entry.file));
+                }
+            }
+        }
+
+        /// Remove audio nobody will ask for again: texts which are no longer in any question,
+        /// audio made with an earlier voice of the current engine, files missing from the
+        /// manifest and old one-off announcements. Audio of other engines is kept so that
+        /// switching back doesn't generate it again. Returns number of files deleted.
+        pub fn collect_garbage(
+            &self,
+            speech: &dyn SpeechSynthesizer,
+            question_texts: &HashSet<String>,
+        ) -> usize {
+            let mut manifest_mutex = match self.manifest.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+            self.merge_saved_manifest(&mut manifest_mutex);
+            let voice = speech.voice();
+            let before = manifest_mutex.entries.len();
+
+            let dir = self.dir.clone();
+            manifest_mutex.entries.retain(|_, entry| {
+                let keep = dir.join(&entry.file).exists()
+                    && match entry.audio_type {
+                        AudioType::NoCache => true,
+                        _ => {
+                            question_texts.contains(&entry.text)
+                                && (entry.engine != speech.name() || entry.voice == voice)
+                        }
+                    };
+                if !keep {
+                    let _ = std::fs::remove_file(dir.join(&entry.file));
+                }
+                keep
+            });
+            self.evict_nocache(&mut manifest_mutex);
+            let mut removed = before - manifest_mutex.entries.len();
+
+            // Audio from before the cache had a manifest, its voice and text are unknown
+            if let Some(audio_dir) = self.dir.parent() {
+                if let Ok(files) = std::fs::read_dir(audio_dir) {
+                    for file in files.flatten() {
+                        let name = file.file_name().to_string_lossy().to_string();
+                        if file.path().is_file()
+                            && is_legacy_audio(&name)
+                            && std::fs::remove_file(file.path()).is_ok()
+                        {
+                            removed += 1;
+                        }
+                    }
+                }
+            }
+
+            // Files left behind by a crash or by hand, except audio being written right now
+            let known: HashSet<&String> = manifest_mutex
+                .entries
+                .values()
+                .map(|entry| &entry.file)
+                .collect();
+            let generating = match self.generating.lock() {
+                Ok(mutex) => mutex.clone(),
+                Err(poisoned_mutex) => poisoned_mutex.into_inner().clone(),
+            };
+            if let Ok(files) = std::fs::read_dir(&self.dir) {
+                for file in files.flatten() {
+                    let name = file.file_name().to_string_lossy().to_string();
+                    if name != MANIFEST_FILE
+                        && !known.contains(&name)
+                        && !generating.iter().any(|key| name.starts_with(key.as_str()))
+                        && std::fs::remove_file(file.path()).is_ok()
+                    {
+                        removed += 1;
+                    }
+                }
+            }
+
+            self.save_manifest(&mut manifest_mutex);
+            removed
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::sync::atomic::AtomicUsize;
+
+        /// Counts how many times audio was made, slowly enough for callers to overlap
+        struct CountingSynthesizer {
+            calls: AtomicUsize,
+        }
+
+        impl SpeechSynthesizer for CountingSynthesizer {
+            fn name(&self) -> &str {
+                "counting"
+            }
+
+            fn voice(&self) -> String {
+                "".to_string()
+            }
+
+            fn extension(&self) -> &str {
+                "wav"
+            }
+
+            fn synthesize(&self, text: &str) -> Result<Option<Vec<u8>>, String> {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                Ok(Some(text.as_bytes().to_vec()))
+            }
+        }
+
+        #[test]
+        fn same_audio_is_generated_once_at_a_time() {
+            let web_dir =
+                std::env::temp_dir().join(format!("pubiq-speech-test-{}", std::process::id()));
+            let cache = SpeechCache::open(&web_dir.to_string_lossy());
+            let speech = CountingSynthesizer {
+                calls: AtomicUsize::new(0),
+            };
+            let files: Vec<Option<String>> = std::thread::scope(|scope| {
+                let handles: Vec<_> = (0..4)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            cache.speak(&speech, "Hei", &QuestionId::default(), AudioType::Question)
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect()
+            });
+            let written = std::fs::read(
+                web_dir
+                    .join(SPEECH_CACHE_DIR)
+                    .join(format!("{}.wav", cache_key(&speech, "Hei"))),
+            );
+            let _ = std::fs::remove_dir_all(&web_dir);
+
+            assert_eq!(speech.calls.load(Ordering::SeqCst), 1);
+            assert!(files[0].is_some());
+            assert!(files.iter().all(|file| *file == files[0]));
+            assert_eq!(written.unwrap(), b"Hei");
+        }
+
+        #[test]
+        fn legacy_audio_is_recognized() {
+            for name in ["q-12.mp3", "a-0.mp3", "nocache-123456.mp3"] {
+                assert!(is_legacy_audio(name), "{}", name);
+            }
+            for name in [
+                "blip.mp3",
+                "tts",
+                "manifest.json",
+                "q-.mp3",
+                "q-12.wav",
+                "q-intro.mp3",
+                "intro-a-1.mp3",
+                "elevenlabs-q-yleinen-3.mp3",
+                "123456.mp3",
+            ] {
+                assert!(!is_legacy_audio(name), "{}", name);
+            }
+        }
+    }
+}
+
+pub mod elevenlabs {
+    use super::speech::SpeechSynthesizer;
+    use serde_json::json;
+
+    const ELEVENLABS_API_ENDPOINT: &str = "https://api.elevenlabs.io/v1/text-to-speech/";
+    const ELEVENLABS_VOICE_ID: &str = "YSabzCJMvEHDduIDMdwV"; // Aurora
+    const ELEVENLABS_MODEL_ID: &str = "eleven_flash_v2_5";
+
+    fn voice_settings() -> serde_json::Value {
+        json!({
+            "stability": 0.51,
+            "similarity_boost": 0.75,
+            "speed": 0.89
+        })
+    }
+
+    pub struct ElevenLabsSynthesizer {
+        api_key: String,
+    }
+
+    impl ElevenLabsSynthesizer {
+        pub fn new(api_key: String) -> ElevenLabsSynthesizer {
+            ElevenLabsSynthesizer { api_key }
+        }
+    }
+
+    impl SpeechSynthesizer for ElevenLabsSynthesizer {
+        fn name(&self) -> &str {
+            "elevenlabs"
+        }
+
+        fn voice(&self) -> String {
+            format!(
+                "{} {} {}",
+                ELEVENLABS_VOICE_ID,
+                ELEVENLABS_MODEL_ID,
+                voice_settings()
+            )
+        }
+
+        fn extension(&self) -> &str {
+            "mp3"
+        }
+
+        fn synthesize(&self, text: &str) -> Result<Option<Vec<u8>>, String> {
+            let body = json!({
+                "text": text,
+                "model_id": ELEVENLABS_MODEL_ID,
+                "voice_settings": voice_settings()
+            })
+            .to_string();
+
+            let mut response = match ureq::post(format!(
+                "{}{}",
+                ELEVENLABS_API_ENDPOINT, ELEVENLABS_VOICE_ID
+            ))
+            .header("xi-api-key", &self.api_key)
+            .header("Content-Type", "application/json")
+            .send(body)
+            {
+                Ok(response) => response,
+                Err(error) => {
+                    return Err(format!("{}", error));
+                }
+            };
+
+            match response.body_mut().read_to_vec() {
+                Ok(vec) => Ok(Some(vec)),
+                Err(error) => Err(format!("{}", error)),
             }
         }
     }
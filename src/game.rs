@@ -1,7 +1,244 @@
+pub mod settings {
+    use super::scoring::ScoringMode;
+    use crate::questions::selection::SelectionMode;
+    use serde::{Deserialize, Serialize};
+
+    /// Per-game tunables, each stage duration is measured from the start of that stage
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct GameSettings {
+        pub introduction_time_ms: u64,
+        pub answer_time_ms: u64,
+        pub finished_time_ms: u64,
+        pub question_limit: u64,
+        /// Only ask questions from these categories, empty means all
+        pub categories: Vec<String>,
+        /// Never ask questions from these categories
+        pub excluded_categories: Vec<String>,
+        /// Only ask questions from these packs, empty means all
+        pub packs: Vec<String>,
+        pub selection: SelectionMode,
+        /// Questions asked in earlier games are not repeated until this many days have passed
+        pub repeat_after_days: u64,
+        pub scoring: ScoringMode,
+        pub team_mode: TeamMode,
+    }
+
+    /// Whether players play alone or in teams, and how a team answers
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum TeamMode {
+        /// No teams, every player scores for themselves
+        #[default]
+        Individual,
+        /// Only first player to join a team answers for it
+        Captain,
+        /// Team answer is the one most members chose
+        MajorityVote,
+    }
+
+    impl Default for GameSettings {
+        fn default() -> GameSettings {
+            GameSettings {
+                introduction_time_ms: 30000,
+                answer_time_ms: 30000,
+                finished_time_ms: 45000,
+                question_limit: 5,
+                categories: vec![],
+                excluded_categories: vec![],
+                packs: vec![],
+                selection: SelectionMode::default(),
+                repeat_after_days: 30,
+                scoring: ScoringMode::default(),
+                team_mode: TeamMode::default(),
+            }
+        }
+    }
+
+    impl GameSettings {
+        /// Check that settings make a playable game
+        pub fn validate(&self) -> Result<(), String> {
+            if self.introduction_time_ms < 1000
+                || self.answer_time_ms < 1000
+                || self.finished_time_ms < 1000
+            {
+                return Err("Stage durations must be at least 1000 ms".to_string());
+            }
+            if self.question_limit == 0 {
+                return Err("Game must have at least one question".to_string());
+            }
+            if self.selection == (SelectionMode::ThemedRounds { round_length: 0 }) {
+                return Err("Themed rounds must be at least one question long".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    pub fn load_settings_from_file(path: &str) -> Option<GameSettings> {
+        let data = match std::fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(_e) => {
+                println!("Settings file ({}) was not found, using defaults.", path);
+                return Some(GameSettings::default());
+            }
+        };
+
+        let settings: GameSettings = match serde_json::from_str(&data) {
+            Ok(val) => val,
+            Err(e) => {
+                eprintln!("Settings file ({}) is invalid.\n\nError: {}", path, e);
+                return None;
+            }
+        };
+
+        match settings.validate() {
+            Ok(_) => Some(settings),
+            Err(e) => {
+                eprintln!("Settings file ({}) is invalid.\n\nError: {}", path, e);
+                None
+            }
+        }
+    }
+}
+
+pub mod scoring {
+    use serde::{Deserialize, Serialize};
+
+    /// Turns one answer into points
+    pub trait ScoringStrategy {
+        /// `time_ms` is when the answer came in, measured from start of answer time.
+        /// `streak` is how many answers in a row before this one were correct.
+        fn score(&self, correct: bool, time_ms: u64, answer_time_ms: u64, streak: u32) -> u32;
+    }
+
+    /// One point per correct answer
+    pub struct ClassicScoring;
+
+    /// Correct answers are worth `max_points` at once, falling linearly to `min_points`
+    /// by the end of answer time
+    pub struct TimeDecayScoring {
+        pub max_points: u32,
+        pub min_points: u32,
+    }
+
+    /// One point per correct answer plus `bonus` for every correct answer before it in a row
+    pub struct StreakBonusScoring {
+        pub bonus: u32,
+    }
+
+    /// Scoring strategy as stored in game settings
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(tag = "mode", rename_all = "snake_case")]
+    pub enum ScoringMode {
+        #[default]
+        Classic,
+        TimeDecay {
+            max_points: u32,
+            min_points: u32,
+        },
+        StreakBonus {
+            bonus: u32,
+        },
+    }
+
+    impl ScoringMode {
+        pub fn strategy(&self) -> Box<dyn ScoringStrategy> {
+            match self {
+                ScoringMode::Classic => Box::new(ClassicScoring),
+                ScoringMode::TimeDecay {
+                    max_points,
+                    min_points,
+                } => Box::new(TimeDecayScoring {
+                    max_points: *max_points,
+                    min_points: *min_points,
+                }),
+                ScoringMode::StreakBonus { bonus } => {
+                    Box::new(StreakBonusScoring { bonus: *bonus })
+                }
+            }
+        }
+    }
+
+    impl ScoringStrategy for ClassicScoring {
+        fn score(&self, correct: bool, _time_ms: u64, _answer_time_ms: u64, _streak: u32) -> u32 {
+            match correct {
+                true => 1,
+                false => 0,
+            }
+        }
+    }
+
+    impl ScoringStrategy for TimeDecayScoring {
+        fn score(&self, correct: bool, time_ms: u64, answer_time_ms: u64, _streak: u32) -> u32 {
+            if !correct {
+                return 0;
+            }
+            if answer_time_ms == 0 {
+                return self.max_points;
+            }
+            let elapsed = time_ms.min(answer_time_ms);
+            let range = self.max_points.saturating_sub(self.min_points) as u64;
+            self.max_points - (range * elapsed / answer_time_ms) as u32
+        }
+    }
+
+    impl ScoringStrategy for StreakBonusScoring {
+        fn score(&self, correct: bool, _time_ms: u64, _answer_time_ms: u64, streak: u32) -> u32 {
+            match correct {
+                true => 1 + self.bonus * streak,
+                false => 0,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn classic_gives_one_point_for_correct_answer() {
+            let scoring = ScoringMode::Classic.strategy();
+            assert_eq!(scoring.score(true, 29000, 30000, 4), 1);
+            assert_eq!(scoring.score(false, 0, 30000, 4), 0);
+        }
+
+        #[test]
+        fn time_decay_falls_linearly_to_minimum() {
+            let scoring = ScoringMode::TimeDecay {
+                max_points: 1000,
+                min_points: 500,
+            }
+            .strategy();
+            assert_eq!(scoring.score(true, 0, 30000, 0), 1000);
+            assert_eq!(scoring.score(true, 15000, 30000, 0), 750);
+            assert_eq!(scoring.score(true, 30000, 30000, 0), 500);
+            // Late answers don't go below the minimum
+            assert_eq!(scoring.score(true, 45000, 30000, 0), 500);
+            assert_eq!(scoring.score(false, 0, 30000, 0), 0);
+            assert_eq!(scoring.score(true, 100, 0, 0), 1000);
+        }
+
+        #[test]
+        fn streak_bonus_grows_with_streak() {
+            let scoring = ScoringMode::StreakBonus { bonus: 1 }.strategy();
+            assert_eq!(scoring.score(true, 0, 30000, 0), 1);
+            assert_eq!(scoring.score(true, 0, 30000, 3), 4);
+            assert_eq!(scoring.score(false, 0, 30000, 3), 0);
+        }
+    }
+}
+
 pub mod state {
     use super::controller::Points;
-    use crate::questions::structure::Question;
+    use super::narration::{question_narrations, Narration};
+    use super::settings::{GameSettings, TeamMode};
+    use crate::helpers::time_helpers::uptime_ms;
+    use crate::questions::{
+        answers::normalize_text,
+        structure::{Question, QuestionId},
+    };
     use core::fmt;
+    use serde::Serialize;
     use uuid::Uuid;
 
     #[derive(Clone, PartialEq)]
@@ -9,13 +246,43 @@ pub mod state {
         pub name: String,
         pub uuid: Uuid,
         pub last_seen: u64,
-        pub score: i32,
+        /// Points so far in the current game, updated when each question closes
+        pub score: u32,
+        pub correct_answers: u32,
+        /// Correct answers in a row up to the latest question
+        pub streak: u32,
+        /// Team given at registration, players without one form a team of their own
+        pub team: Option<String>,
     }
 
-    #[derive(PartialEq)]
+    /// What tells teams apart. A player without a team is a team of their own, keyed by
+    /// uuid so that it is never merged with a registered team of the same name.
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum TeamKey {
+        Registered(String),
+        Solo(Uuid),
+    }
+
+    /// Players answering together, formed when the game starts
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Team {
+        pub key: TeamKey,
+        /// Registered team name, or the player's name for a team of one
+        pub name: String,
+        /// First member to join, the only one who answers in captain mode
+        pub captain: Uuid,
+        pub members: Vec<Uuid>,
+        pub score: u32,
+        pub correct_answers: u32,
+        pub streak: u32,
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
     pub enum QuestionStage {
         QuestionIntroduction,
         QuestionAnswerTime,
+        /// Presenter decides on borderline typed answers before points are given
+        QuestionReview,
         QuestionFinished,
     }
 
@@ -24,13 +291,14 @@ pub mod state {
             let display = match *self {
                 QuestionStage::QuestionIntroduction => "QuestionIntroduction".to_string(),
                 QuestionStage::QuestionAnswerTime => "QuestionAnswerTime".to_string(),
+                QuestionStage::QuestionReview => "QuestionReview".to_string(),
                 QuestionStage::QuestionFinished => "QuestionFinished".to_string(),
             };
             write!(f, "{}", display)
         }
     }
 
-    #[derive(PartialEq)]
+    #[derive(Clone, Copy, PartialEq)]
     pub enum GameStage {
         WaitingForPlayers,
         IntroducePlayers,
@@ -59,19 +327,196 @@ pub mod state {
         pub question_number: u64,
         pub question_stage: QuestionStage,
         pub question_start_time: u64,
+        pub stage_start_time: u64,
         pub question_limit: u64,
+        pub settings: GameSettings,
         pub players: Vec<Player>,
-        pub proceed_flag: bool,
-        pub newgame_flag: bool,
+        /// Empty unless game is played in teams
+        pub teams: Vec<Team>,
         pub audio: Option<String>,
         pub tts_text: Option<String>,
+        /// Audio or host text of the current stage is still being generated
+        pub generating: bool,
+        /// Narration waiting to be handed over to a background job
+        pub pending_narration: Option<Narration>,
+        /// Grows with every narration so that late results of earlier ones can be discarded
+        pub narration_id: u64,
+        /// Questions picked for this game in the order they are asked
+        pub game_questions: Vec<Question>,
+        /// Audio of this game's questions waiting to be handed over to a background job
+        pub pending_preparation: Vec<Narration>,
+        /// Grows with every game so that audio preparation of earlier games is not counted
+        pub preparation_id: u64,
+        /// Audio of this game's questions and answers generated so far
+        pub audio_prepared: u64,
+        pub audio_to_prepare: u64,
+        /// Ranked leaderboard, updated when each question closes
         pub scores: Vec<Points>,
+        /// How the room answered the current question, set when the question finishes
+        pub reveal: Option<RevealStats>,
+        /// Typed answers to the current question which presenter has to accept or reject
+        pub review: Vec<AnswerReview>,
+        /// Why the last presenter action failed, shown on presenter screen
+        pub error: Option<String>,
+    }
+
+    impl Default for GameState {
+        /// Fresh state for a game that is waiting for players
+        fn default() -> GameState {
+            GameState {
+                game_stage: GameStage::WaitingForPlayers,
+                question: Question::default(),
+                question_number: 0,
+                question_stage: QuestionStage::QuestionIntroduction,
+                question_start_time: 0,
+                stage_start_time: 0,
+                question_limit: 5,
+                settings: GameSettings::default(),
+                players: vec![],
+                teams: vec![],
+                audio: None,
+                tts_text: None,
+                generating: false,
+                pending_narration: None,
+                narration_id: 0,
+                game_questions: vec![],
+                pending_preparation: vec![],
+                preparation_id: 0,
+                audio_prepared: 0,
+                audio_to_prepare: 0,
+                scores: vec![],
+                reveal: None,
+                review: vec![],
+                error: None,
+            }
+        }
+    }
+
+    impl GameState {
+        /// Replace current audio and host text with a narration generated in the background
+        pub fn request_narration(&mut self, narration: Narration) {
+            self.narration_id += 1;
+            self.audio = None;
+            self.tts_text = None;
+            self.generating = true;
+            self.pending_narration = Some(narration);
+        }
+
+        /// Generate audio for every question and answer of this game in the background
+        pub fn request_preparation(&mut self) {
+            self.preparation_id += 1;
+            self.pending_preparation = question_narrations(&self.game_questions);
+            self.audio_prepared = 0;
+            self.audio_to_prepare = self.pending_preparation.len() as u64;
+        }
+
+        /// Move to another question stage and restart the stage clock at `now` (uptime, ms)
+        pub fn set_question_stage(&mut self, question_stage: QuestionStage, now: u64) {
+            self.question_stage = question_stage;
+            self.stage_start_time = now;
+        }
+
+        /// Point in time (uptime, ms) at which the current stage times out, if it does
+        pub fn stage_deadline(&self) -> Option<u64> {
+            if self.game_stage != GameStage::GameInProgress {
+                return None;
+            }
+            let duration = match self.question_stage {
+                QuestionStage::QuestionIntroduction => self.settings.introduction_time_ms,
+                QuestionStage::QuestionAnswerTime => self.settings.answer_time_ms,
+                // Waits for presenter however long it takes
+                QuestionStage::QuestionReview => return None,
+                QuestionStage::QuestionFinished => self.settings.finished_time_ms,
+            };
+            Some(self.stage_start_time + duration)
+        }
+
+        /// Team the player belongs to in this game
+        pub fn team_of(&self, uuid: Uuid) -> Option<&Team> {
+            self.teams.iter().find(|team| team.members.contains(&uuid))
+        }
+
+        /// Captain mode lets only captains answer, otherwise everyone answers
+        pub fn may_answer(&self, uuid: Uuid) -> bool {
+            match self.settings.team_mode {
+                TeamMode::Captain => match self.team_of(uuid) {
+                    Some(team) => team.captain == uuid,
+                    None => false,
+                },
+                _ => true,
+            }
+        }
+
+        /// How many answers there will be to a question when everyone has answered
+        pub fn expected_answers(&self) -> u64 {
+            match self.settings.team_mode {
+                TeamMode::Captain => self.teams.len() as u64,
+                _ => self.players.len() as u64,
+            }
+        }
+
+        /// Milliseconds left in the current stage, for countdowns in the frontends
+        pub fn stage_time_remaining(&self) -> Option<u64> {
+            self.stage_deadline()
+                .map(|deadline| deadline.saturating_sub(uptime_ms()))
+        }
+    }
+
+    /// Number of players who picked one answer option
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct AnswerCount {
+        pub answer: String,
+        pub count: u32,
+    }
+
+    /// Outcome of the finished question for one player
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct PlayerResult {
+        pub player_uuid: Uuid,
+        pub correct: bool,
+    }
+
+    /// Borderline typed answer waiting for presenter's decision. Answers which
+    /// normalize to the same text are reviewed together.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct AnswerReview {
+        pub answer: String,
+        /// Accepted spelling the answer was closest to
+        pub closest_accepted: String,
+        pub players: Vec<String>,
+        /// None until presenter decides, undecided answers are rejected
+        pub accepted: Option<bool>,
+    }
+
+    impl AnswerReview {
+        /// Presenter's decision on an answer, None if it was not up for review
+        pub fn decision(review: &[AnswerReview], answer: &str) -> Option<bool> {
+            let answer = normalize_text(answer);
+            review
+                .iter()
+                .find(|review| review.answer == answer)
+                .map(|review| review.accepted.unwrap_or(false))
+        }
+    }
+
+    /// Answer statistics shown when the correct answer is revealed
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct RevealStats {
+        /// One entry per answer option, in the order players saw them
+        pub answer_counts: Vec<AnswerCount>,
+        pub correct_players: Vec<String>,
+        pub fastest_player: Option<String>,
+        pub fastest_time_ms: Option<u64>,
+        #[serde(skip)]
+        pub player_results: Vec<PlayerResult>,
     }
 
     #[derive(Debug, Clone)]
     pub struct Questions {
         pub question_number: u64,
-        pub question_id: i64,
+        pub question_id: QuestionId,
+        /// Category the question was picked for by selection strategy
+        pub category: Option<String>,
     }
 
     #[derive(Debug, Clone)]
@@ -79,288 +524,823 @@ pub mod state {
         pub question_number: u64,
         pub answer: String,
         pub player_uuid: Uuid,
+        /// Milliseconds from start of answer time to submission
+        pub time_ms: u64,
     }
 }
 
-pub mod controller {
-    use super::state::{Answers, GameStage, GameState, QuestionStage, Questions};
+pub mod events {
+    /// Everything that can move the game controller forward
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum GameEvent {
+        /// Presenter wants to move on
+        Proceed,
+        /// Presenter wants to start over after results
+        NewGame,
+        /// A player has submitted an answer
+        AnswerSubmitted,
+        /// A player has registered
+        PlayerJoined,
+        /// Presenter has changed game settings
+        SettingsChanged,
+        /// Presenter has accepted or rejected a typed answer
+        AnswerReviewed,
+        /// Current stage has run out of time
+        Deadline,
+        /// Background narration has finished
+        NarrationReady,
+        /// Audio for one more upcoming question or answer has been generated
+        AudioPrepared,
+        /// Room has been closed, controller stops
+        CloseRoom,
+    }
+}
+
+pub mod narration {
+    use super::events::GameEvent;
     use crate::{
         external_apis::{
-            elevenlabs::{generate_speech, AudioType},
-            google::prompt_gemini,
-        },
-        helpers::{
-            natural_language::{
-                correct_answer_and_context_announcement, get_player_names_for_tts,
-                prompt_for_player_introduction, prompt_for_winner_announcement,
-            },
-            time_helpers::uptime_ms,
+            commentary::{commentate, CommentaryRequest},
+            host::Host,
+            speech_cache::AudioType,
         },
-        questions::structure::{Question, Root},
+        helpers::natural_language::correct_answer_and_context_announcement,
+        questions::structure::{Question, QuestionId},
+        rooms::registry::Room,
     };
-    use rand::{seq::SliceRandom, Rng};
     use std::{
+        collections::HashSet,
         sync::{Arc, Mutex},
         thread,
+    };
+
+    /// How many texts are sent to the speech engine at the same time when preparing audio
+    const PREPARATION_THREADS: usize = 4;
+
+    /// Speech or host commentary generated while the game goes on
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum Narration {
+        Question {
+            text: String,
+            question_id: QuestionId,
+        },
+        Answer {
+            text: String,
+            question_id: QuestionId,
+        },
+        /// Host's own words, not cached as they differ every time
+        Commentary(CommentaryRequest),
+    }
+
+    impl Narration {
+        pub fn question(question: &Question) -> Narration {
+            Narration::Question {
+                text: question.question_tts.clone(),
+                question_id: question.qualified_id(),
+            }
+        }
+
+        pub fn answer(question: &Question) -> Narration {
+            Narration::Answer {
+                text: correct_answer_and_context_announcement(
+                    &question.correct_tts,
+                    &question.context_information_tts,
+                ),
+                question_id: question.qualified_id(),
+            }
+        }
+    }
+
+    /// Question and answer narrations of the questions in the order they are heard
+    pub fn question_narrations(questions: &[Question]) -> Vec<Narration> {
+        questions
+            .iter()
+            .flat_map(|question| [Narration::question(question), Narration::answer(question)])
+            .collect()
+    }
+
+    /// Remove cached audio which none of the questions uses anymore
+    pub fn clean_speech_cache(host: &Host, questions: &[Question]) {
+        let texts: HashSet<String> = question_narrations(questions)
+            .into_iter()
+            .filter_map(|narration| match narration {
+                Narration::Question { text, .. } | Narration::Answer { text, .. } => Some(text),
+                Narration::Commentary(_) => None,
+            })
+            .collect();
+        let removed = host
+            .speech_cache
+            .collect_garbage(host.speech.as_ref(), &texts);
+        if removed > 0 {
+            println!("Removed {} unused audio files", removed);
+        }
+    }
+
+    /// Generate audio for the narrations using several threads, returns when all are done.
+    /// `on_ready` is told after each one whether audio was produced.
+    pub fn prepare_narrations(
+        host: &Host,
+        narrations: Vec<Narration>,
+        on_ready: &(dyn Fn(bool) + Sync),
+    ) {
+        let queue = Mutex::new(narrations.into_iter());
+        thread::scope(|scope| {
+            for _ in 0..PREPARATION_THREADS {
+                scope.spawn(|| loop {
+                    let next = match queue.lock() {
+                        Ok(mut mutex) => mutex.next(),
+                        Err(poisoned_mutex) => poisoned_mutex.into_inner().next(),
+                    };
+                    match next {
+                        Some(narration) => {
+                            let (_, audio) = narrate(host, &narration, "");
+                            on_ready(audio.is_some());
+                        }
+                        None => break,
+                    }
+                });
+            }
+        });
+    }
+
+    /// Host text and audio for the narration, either may be missing
+    fn narrate(
+        host: &Host,
+        narration: &Narration,
+        room_code: &str,
+    ) -> (Option<String>, Option<String>) {
+        match narration {
+            Narration::Question { text, question_id } => {
+                (None, host.speak(text, question_id, AudioType::Question))
+            }
+            Narration::Answer { text, question_id } => {
+                (None, host.speak(text, question_id, AudioType::Answer))
+            }
+            Narration::Commentary(request) => {
+                let tts_text = commentate(host.commentary.as_ref(), request);
+                let audio = host.announce(&tts_text, room_code);
+                (Some(tts_text), audio)
+            }
+        }
+    }
+
+    /// Generate narration in its own thread without holding any room locks meanwhile. The
+    /// result is attached to game state unless a newer narration has been requested.
+    pub fn start_narration_job(
+        room: &Arc<Room>,
+        host: &Host,
+        narration: Narration,
+        narration_id: u64,
+    ) {
+        let room = room.clone();
+        let host = host.clone();
+        let builder = thread::Builder::new().name(format!("Narration {}", room.code));
+        let result = builder.spawn(move || {
+            let (tts_text, audio) = narrate(&host, &narration, &room.code);
+            {
+                let mut game_state_mutex = match room.game_state.lock() {
+                    Ok(mutex) => mutex,
+                    Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+                };
+                if game_state_mutex.narration_id != narration_id {
+                    println!("Narration {} is no longer needed", narration_id);
+                    return;
+                }
+                game_state_mutex.tts_text = tts_text;
+                game_state_mutex.audio = audio;
+                game_state_mutex.generating = false;
+            }
+            let _ = room.events_tx.send(GameEvent::NarrationReady);
+        });
+        if let Err(error) = result {
+            eprintln!("Unable to start narration: {}", error);
+        }
+    }
+
+    /// Generate audio of the game's questions ahead of time so that they are ready when asked
+    pub fn start_audio_preparation(
+        room: &Arc<Room>,
+        host: &Host,
+        narrations: Vec<Narration>,
+        preparation_id: u64,
+    ) {
+        let room = room.clone();
+        let host = host.clone();
+        let builder = thread::Builder::new().name(format!("Audio preparation {}", room.code));
+        let result = builder.spawn(move || {
+            println!(
+                "Preparing {} audio files for room {}",
+                narrations.len(),
+                room.code
+            );
+            prepare_narrations(&host, narrations, &|_| {
+                {
+                    let mut game_state_mutex = match room.game_state.lock() {
+                        Ok(mutex) => mutex,
+                        Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+                    };
+                    if game_state_mutex.preparation_id != preparation_id {
+                        return;
+                    }
+                    game_state_mutex.audio_prepared += 1;
+                }
+                let _ = room.events_tx.send(GameEvent::AudioPrepared);
+            });
+        });
+        if let Err(error) = result {
+            eprintln!("Unable to start audio preparation: {}", error);
+        }
+    }
+}
+
+pub mod controller {
+    use super::{
+        events::GameEvent,
+        narration::{start_audio_preparation, start_narration_job, Narration},
+        settings::{GameSettings, TeamMode},
+        state::{
+            AnswerCount, AnswerReview, Answers, GameStage, GameState, PlayerResult, QuestionStage,
+            Questions, RevealStats, Team, TeamKey,
+        },
+    };
+    use crate::{
+        external_apis::{commentary::CommentaryRequest, host::Host},
+        helpers::time_helpers::{unix_time_s, uptime_ms},
+        questions::{
+            answers::{normalize_text, TextMatch},
+            bank::BankHandle,
+            history::QuestionHistory,
+            selection::{pack_allowed, question_allowed},
+            structure::{Question, QuestionBank, QuestionId},
+        },
+        rooms::registry::Room,
+    };
+    use std::{
+        sync::{
+            mpsc::{Receiver, RecvTimeoutError, Sender},
+            Arc, Mutex,
+        },
         time::Duration,
     };
+    use uuid::Uuid;
 
+    /// One row of the leaderboard, either a player or a team
     #[derive(Clone, Debug, PartialEq, serde::Serialize)]
     pub struct Points {
-        pub player_name: String,
+        /// None for teams
+        #[serde(skip)]
+        pub player_uuid: Option<Uuid>,
+        /// None for players
+        #[serde(skip)]
+        pub team: Option<TeamKey>,
+        /// Player or team name
+        pub name: String,
+        /// Names of team members, empty for players
+        pub members: Vec<String>,
         pub points: u32,
+        pub correct_answers: u32,
+        /// Players with equal points share a position
+        pub position: u32,
+        /// Positions gained (positive) or lost (negative) since previous question
+        pub position_change: i32,
     }
 
     pub fn run_game_controller(
-        game_state: Arc<Mutex<GameState>>,
-        questions: Arc<Mutex<Vec<Questions>>>,
-        answers: Arc<Mutex<Vec<Answers>>>,
-        all_questions: &Root,
+        room: Arc<Room>,
+        question_bank: Arc<BankHandle>,
+        host: Host,
+        history: Arc<Mutex<QuestionHistory>>,
+        events_rx: Receiver<GameEvent>,
+        state_changed_tx: Sender<String>,
     ) {
-        println!("Game controller started");
+        println!("Game controller started for room {}", room.code);
+        let mut all_questions = question_bank.current();
         loop {
-            {
-                let mut game_state_mutex = match game_state.lock() {
+            let deadline = {
+                let game_state_mutex = match room.game_state.lock() {
                     Ok(mutex) => mutex,
                     Err(poisoned_mutex) => poisoned_mutex.into_inner(),
                 };
+                game_state_mutex.stage_deadline()
+            };
+
+            // Sleep until something happens or the current stage runs out of time
+            let event = match deadline {
+                Some(deadline) => {
+                    let timeout = Duration::from_millis(deadline.saturating_sub(uptime_ms()));
+                    match events_rx.recv_timeout(timeout) {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Timeout) => GameEvent::Deadline,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                None => match events_rx.recv() {
+                    Ok(event) => event,
+                    Err(_) => break,
+                },
+            };
 
-                let answers_mutex = match answers.lock() {
+            if event == GameEvent::CloseRoom {
+                break;
+            }
+
+            let work = {
+                let mut game_state_mutex = match room.game_state.lock() {
                     Ok(mutex) => mutex,
                     Err(poisoned_mutex) => poisoned_mutex.into_inner(),
                 };
 
-                match game_state_mutex.game_stage {
-                    GameStage::WaitingForPlayers => {
-                        if game_state_mutex.proceed_flag {
-                            game_state_mutex.game_stage = GameStage::IntroducePlayers;
-                            game_state_mutex.proceed_flag = false;
-                            println!(
-                                "Proceed triggered -- state {}",
-                                game_state_mutex.game_stage.to_string()
-                            );
-                            game_state_mutex.question_number = 1;
-                            game_state_mutex.question = get_new_question(
-                                all_questions,
-                                questions.clone(),
-                                game_state_mutex.question_number,
-                            );
-                            game_state_mutex.question_stage = QuestionStage::QuestionIntroduction;
-                            game_state_mutex.question.answer_options =
-                                Some(shuffle_answers(&game_state_mutex.question));
-
-                            let mut list_of_players: Vec<String> = vec![];
-                            for player in game_state_mutex.players.iter() {
-                                list_of_players.push(player.name.clone());
-                            }
-                            match prompt_gemini(prompt_for_player_introduction(
-                                get_player_names_for_tts(list_of_players),
-                            )) {
-                                Ok(tts_text) => {
-                                    match generate_speech(&tts_text, &0, AudioType::NoCache) {
-                                        Ok(audio_filename) => {
-                                            game_state_mutex.tts_text = Some(tts_text);
-                                            game_state_mutex.audio = Some(audio_filename);
-                                        }
-                                        Err(_) => {
-                                            game_state_mutex.tts_text = None;
-                                            game_state_mutex.audio = None;
-                                        }
-                                    };
-                                }
-                                Err(_) => {
-                                    game_state_mutex.tts_text = None;
-                                    game_state_mutex.audio = None;
-                                }
-                            };
+                let mut answers_mutex = match room.answers.lock() {
+                    Ok(mutex) => mutex,
+                    Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+                };
+
+                // Reloaded question bank is only taken into use between games
+                if game_state_mutex.game_stage == GameStage::WaitingForPlayers {
+                    all_questions = question_bank.current();
+                }
+
+                handle_event(
+                    &mut game_state_mutex,
+                    &room.questions,
+                    &history,
+                    &mut answers_mutex,
+                    &all_questions,
+                    event,
+                    uptime_ms(),
+                );
+
+                let narration = game_state_mutex
+                    .pending_narration
+                    .take()
+                    .map(|narration| (narration, game_state_mutex.narration_id));
+                let preparation = std::mem::take(&mut game_state_mutex.pending_preparation);
+                (narration, preparation, game_state_mutex.preparation_id)
+            };
+
+            // Locks are released so that slow speech and commentary don't hold up requests
+            let (narration, preparation, preparation_id) = work;
+            if let Some((narration, narration_id)) = narration {
+                start_narration_job(&room, &host, narration, narration_id);
+            }
+            if !preparation.is_empty() {
+                start_audio_preparation(&room, &host, preparation, preparation_id);
+            }
+
+            // Let the REST API push the new state to clients
+            let _ = state_changed_tx.send(room.code.clone());
+        }
+        println!("Game controller stopped for room {}", room.code);
+        let _ = state_changed_tx.send(room.code.clone());
+    }
+
+    /// Advance the game state machine by one event which happened at `now` (uptime, ms)
+    pub fn handle_event(
+        game_state: &mut GameState,
+        questions: &Arc<Mutex<Vec<Questions>>>,
+        history: &Arc<Mutex<QuestionHistory>>,
+        answers: &mut Vec<Answers>,
+        all_questions: &QuestionBank,
+        event: GameEvent,
+        now: u64,
+    ) {
+        // Deadline events may arrive late or early, only trust the clock
+        let deadline_passed = match game_state.stage_deadline() {
+            Some(deadline) => event == GameEvent::Deadline && now >= deadline,
+            None => false,
+        };
+
+        match game_state.game_stage {
+            GameStage::WaitingForPlayers => {
+                if event == GameEvent::Proceed {
+                    match start_game(game_state, questions, history, answers, all_questions) {
+                        Ok(_) => {
+                            game_state.error = None;
+                            game_state.game_stage = GameStage::IntroducePlayers;
+                            println!("Proceed triggered -- state {}", game_state.game_stage);
                         }
-                    }
-                    GameStage::IntroducePlayers => {
-                        if game_state_mutex.proceed_flag {
-                            println!(
-                                "Proceed triggered -- state {}",
-                                game_state_mutex.game_stage.to_string()
-                            );
-                            game_state_mutex.proceed_flag = false;
-                            match generate_speech(
-                                &game_state_mutex.question.question_tts,
-                                &game_state_mutex.question.id,
-                                AudioType::Question,
-                            ) {
-                                Ok(audio_filename) => {
-                                    game_state_mutex.audio = Some(audio_filename);
-                                }
-                                Err(_) => {
-                                    game_state_mutex.audio = None;
-                                }
-                            };
-                            game_state_mutex.game_stage = GameStage::GameInProgress;
-                            game_state_mutex.question_start_time = uptime_ms();
+                        Err(error) => {
+                            eprintln!("Unable to start game: {}", error);
+                            game_state.error = Some(error);
                         }
                     }
-                    GameStage::GameInProgress => match game_state_mutex.question_stage {
-                        QuestionStage::QuestionIntroduction => {
-                            if game_state_mutex.proceed_flag
-                                || uptime_ms() > game_state_mutex.question_start_time + 30000
-                            {
-                                println!(
-                                    "Proceed triggered -- state {}",
-                                    game_state_mutex.game_stage.to_string()
-                                );
-                                game_state_mutex.proceed_flag = false;
-                                game_state_mutex.question_stage = QuestionStage::QuestionAnswerTime;
-                            }
-                        }
-                        QuestionStage::QuestionAnswerTime => {
-                            game_state_mutex.proceed_flag = false;
-                            if count_players_answered_to_question(
-                                &answers_mutex,
-                                game_state_mutex.question_number,
-                            ) == game_state_mutex.players.len() as u64
-                                || uptime_ms() > game_state_mutex.question_start_time + 60000
-                            {
-                                println!(
-                                    "Proceed triggered -- state {}",
-                                    game_state_mutex.game_stage.to_string()
-                                );
-                                game_state_mutex.question_stage = QuestionStage::QuestionFinished;
-                                match generate_speech(
-                                    &correct_answer_and_context_announcement(
-                                        &game_state_mutex.question.correct_tts,
-                                        &game_state_mutex.question.context_information_tts,
-                                    ),
-                                    &game_state_mutex.question.id,
-                                    AudioType::Answer,
-                                ) {
-                                    Ok(audio_filename) => {
-                                        game_state_mutex.audio = Some(audio_filename);
-                                    }
-                                    Err(_) => {
-                                        game_state_mutex.audio = None;
-                                    }
-                                };
-                            }
-                        }
-                        QuestionStage::QuestionFinished => {
-                            if game_state_mutex.proceed_flag
-                                || uptime_ms() > game_state_mutex.question_start_time + 45000
-                            {
-                                game_state_mutex.proceed_flag = false;
-                                game_state_mutex.question_number += 1;
-                                if game_state_mutex.question_number
-                                    > game_state_mutex.question_limit
-                                {
-                                    println!("Game finished!");
-                                    game_state_mutex.game_stage = GameStage::ResultsShow;
-                                    let _scores = match count_points(
-                                        all_questions,
-                                        &game_state_mutex,
-                                        questions.clone(),
-                                        &answers_mutex,
-                                    ) {
-                                        Some(scores) => {
-                                            let winner_name = &scores[0].player_name;
-                                            let winner_points = &scores[0].points;
-                                            match prompt_gemini(prompt_for_winner_announcement(
-                                                winner_name.to_string(),
-                                                winner_points.to_string(),
-                                            )) {
-                                                Ok(tts_text) => {
-                                                    match generate_speech(
-                                                        &tts_text,
-                                                        &0,
-                                                        AudioType::NoCache,
-                                                    ) {
-                                                        Ok(audio_filename) => {
-                                                            game_state_mutex.tts_text =
-                                                                Some(tts_text);
-                                                            game_state_mutex.audio =
-                                                                Some(audio_filename);
-                                                        }
-                                                        Err(_) => {
-                                                            game_state_mutex.tts_text = None;
-                                                            game_state_mutex.audio = None;
-                                                        }
-                                                    };
-                                                }
-                                                Err(_) => {
-                                                    game_state_mutex.tts_text = None;
-                                                    game_state_mutex.audio = None;
-                                                }
-                                            };
-                                            game_state_mutex.scores = scores.clone();
-                                            scores
-                                        }
-                                        None => vec![],
-                                    };
-                                } else {
-                                    println!("\nNew question\n");
-                                    game_state_mutex.question = get_new_question(
-                                        all_questions,
-                                        questions.clone(),
-                                        game_state_mutex.question_number,
-                                    );
-                                    match generate_speech(
-                                        &game_state_mutex.question.question_tts,
-                                        &game_state_mutex.question.id,
-                                        AudioType::Question,
-                                    ) {
-                                        Ok(audio_filename) => {
-                                            game_state_mutex.audio = Some(audio_filename);
-                                        }
-                                        Err(_) => {
-                                            game_state_mutex.audio = None;
-                                        }
-                                    };
-                                    game_state_mutex.question_stage =
-                                        QuestionStage::QuestionIntroduction;
-                                    game_state_mutex.question_start_time = uptime_ms();
-                                    game_state_mutex.question.answer_options =
-                                        Some(shuffle_answers(&game_state_mutex.question))
+                }
+            }
+            GameStage::IntroducePlayers => {
+                if event == GameEvent::Proceed {
+                    println!("Proceed triggered -- state {}", game_state.game_stage);
+                    record_question_asked(game_state, history);
+                    announce_question(game_state);
+                    game_state.game_stage = GameStage::GameInProgress;
+                    game_state.question_start_time = now;
+                    game_state.set_question_stage(QuestionStage::QuestionIntroduction, now);
+                }
+            }
+            GameStage::GameInProgress => match game_state.question_stage {
+                QuestionStage::QuestionIntroduction => {
+                    if event == GameEvent::Proceed || deadline_passed {
+                        println!("Proceed triggered -- state {}", game_state.game_stage);
+                        game_state.set_question_stage(QuestionStage::QuestionAnswerTime, now);
+                    }
+                }
+                // Proceed is ignored here, answer time ends when everyone has answered
+                QuestionStage::QuestionAnswerTime => {}
+                QuestionStage::QuestionReview => {
+                    if event == GameEvent::Proceed {
+                        println!("Proceed triggered -- state {}", game_state.game_stage);
+                        finish_question(game_state, answers, now);
+                    }
+                }
+                QuestionStage::QuestionFinished => {
+                    if event == GameEvent::Proceed || deadline_passed {
+                        game_state.question_number += 1;
+                        if game_state.question_number > game_state.question_limit {
+                            println!("Game finished!");
+                            game_state.game_stage = GameStage::ResultsShow;
+                            announce_winner(game_state);
+                        } else {
+                            println!("\nNew question\n");
+                            match next_question(game_state, now) {
+                                Ok(_) => {
+                                    record_question_asked(game_state, history);
+                                    announce_question(game_state);
+                                }
+                                Err(error) => {
+                                    // Should not happen as pool is checked at start, end game early
+                                    eprintln!("{}, ending game", error);
+                                    game_state.question_limit = game_state.question_number - 1;
+                                    game_state.game_stage = GameStage::ResultsShow;
+                                    announce_winner(game_state);
                                 }
                             }
                         }
-                    },
-                    GameStage::ResultsShow => {
-                        if game_state_mutex.newgame_flag == true {
-                            let new_game: GameState = GameState {
-                                game_stage: GameStage::WaitingForPlayers,
-                                question: Question {
-                                    id: 0,
-                                    category: vec![],
-                                    question: "".to_string(),
-                                    question_tts: "".to_string(),
-                                    context_information: "".to_string(),
-                                    context_information_tts: "".to_string(),
-                                    correct: "".to_string(),
-                                    correct_tts: "".to_string(),
-                                    incorrect_1: "".to_string(),
-                                    incorrect_2: "".to_string(),
-                                    incorrect_3: "".to_string(),
-                                    answer_options: None,
-                                },
-                                question_number: 0,
-                                question_stage: QuestionStage::QuestionIntroduction,
-                                question_start_time: 0,
-                                question_limit: 5,
-                                players: vec![],
-                                proceed_flag: false,
-                                newgame_flag: false,
-                                audio: None,
-                                tts_text: None,
-                                scores: vec![],
-                            };
-                            *game_state_mutex = new_game;
-                        }
                     }
-                    //GameStage::GameFinished => todo!(),
                 }
+            },
+            GameStage::ResultsShow => {
+                if event == GameEvent::NewGame {
+                    // Settings chosen by presenter carry over to the next game
+                    let settings = game_state.settings.clone();
+                    let narration_id = game_state.narration_id;
+                    let preparation_id = game_state.preparation_id;
+                    *game_state = GameState::default();
+                    game_state.settings = settings;
+                    game_state.narration_id = narration_id;
+                    game_state.preparation_id = preparation_id;
+                }
+            }
+        }
+
+        // Answer time may end as a result of any event, e.g. the last player answering
+        if game_state.game_stage == GameStage::GameInProgress
+            && game_state.question_stage == QuestionStage::QuestionAnswerTime
+        {
+            let deadline_passed = match game_state.stage_deadline() {
+                Some(deadline) => now >= deadline,
+                None => false,
+            };
+            if count_players_answered_to_question(answers, game_state.question_number)
+                == game_state.expected_answers()
+                || deadline_passed
+            {
+                println!("Proceed triggered -- state {}", game_state.game_stage);
+                game_state.review = answers_to_review(game_state, answers);
+                if game_state.review.is_empty() {
+                    finish_question(game_state, answers, now);
+                } else {
+                    game_state.set_question_stage(QuestionStage::QuestionReview, now);
+                }
+            }
+        }
+    }
+
+    /// Give points and reveal the correct answer
+    fn finish_question(game_state: &mut GameState, answers: &[Answers], now: u64) {
+        game_state.set_question_stage(QuestionStage::QuestionFinished, now);
+        score_question(game_state, answers);
+        game_state.scores = rank_players(game_state);
+        game_state.reveal = Some(reveal_stats(game_state, answers));
+        announce_answer(game_state);
+    }
+
+    /// Borderline typed answers to the current question, grouped by normalized text
+    fn answers_to_review(game_state: &GameState, answers: &[Answers]) -> Vec<AnswerReview> {
+        let mut review: Vec<AnswerReview> = vec![];
+        for answer in answers
+            .iter()
+            .filter(|answer| answer.question_number == game_state.question_number)
+        {
+            let closest_accepted = match game_state.question.text_match(&answer.answer) {
+                Some((TextMatch::Borderline, closest_accepted)) => closest_accepted,
+                _ => continue,
+            };
+            let player_name = match game_state
+                .players
+                .iter()
+                .find(|player| player.uuid == answer.player_uuid)
+            {
+                Some(player) => player.name.clone(),
+                None => continue,
+            };
+            let normalized = normalize_text(&answer.answer);
+            match review.iter_mut().find(|review| review.answer == normalized) {
+                Some(review) => review.players.push(player_name),
+                None => review.push(AnswerReview {
+                    answer: normalized,
+                    closest_accepted,
+                    players: vec![player_name],
+                    accepted: None,
+                }),
+            }
+        }
+        review
+    }
+
+    /// Presenter's decision wins over automatic checking
+    fn judge_answer(
+        question: &Question,
+        review: &[AnswerReview],
+        answer: &str,
+        all_answers: &[&str],
+    ) -> bool {
+        match AnswerReview::decision(review, answer) {
+            Some(accepted) => accepted,
+            None => question.is_correct(answer, all_answers),
+        }
+    }
+
+    fn start_game(
+        game_state: &mut GameState,
+        questions: &Arc<Mutex<Vec<Questions>>>,
+        history: &Arc<Mutex<QuestionHistory>>,
+        answers: &mut Vec<Answers>,
+        all_questions: &QuestionBank,
+    ) -> Result<(), String> {
+        // Asked questions and answers only describe the current game
+        answers.clear();
+        game_state.scores.clear();
+        for player in game_state.players.iter_mut() {
+            player.score = 0;
+            player.correct_answers = 0;
+            player.streak = 0;
+        }
+        game_state.teams = match game_state.settings.team_mode {
+            TeamMode::Individual => vec![],
+            _ => form_teams(game_state),
+        };
+
+        // Pool may have shrunk since settings were chosen, play what is left
+        let available = {
+            let mut questions_mutex = match questions.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+            questions_mutex.clear();
+
+            let history_mutex = match history.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+            count_available_questions(
+                all_questions,
+                &questions_mutex,
+                &history_mutex,
+                &game_state.settings,
+            )
+        };
+        if available == 0 {
+            return Err(format!(
+                "Question pool exhausted, every matching question has been asked within {} days",
+                game_state.settings.repeat_after_days
+            ));
+        }
+        game_state.question_limit = game_state.settings.question_limit.min(available);
+        if game_state.question_limit < game_state.settings.question_limit {
+            println!(
+                "Only {} unasked questions left, game will be shorter",
+                game_state.question_limit
+            );
+        }
+
+        // Whole game is picked at once so that its audio can be generated in advance
+        game_state.game_questions.clear();
+        for question_number in 1..=game_state.question_limit {
+            match get_new_question(
+                all_questions,
+                questions.clone(),
+                history,
+                question_number,
+                &game_state.settings,
+            ) {
+                Some(question) => game_state.game_questions.push(question),
+                None => break,
+            }
+        }
+        game_state.question_limit = game_state.game_questions.len() as u64;
+        game_state.request_preparation();
+
+        game_state.question_number = 1;
+        game_state.question = game_state
+            .game_questions
+            .first()
+            .cloned()
+            .ok_or("Question pool exhausted")?;
+        game_state.question_stage = QuestionStage::QuestionIntroduction;
+        game_state.question.answer_options = game_state.question.shuffled_answer_options();
+
+        let mut list_of_players: Vec<String> = vec![];
+        for player in game_state.players.iter() {
+            list_of_players.push(player.name.clone());
+        }
+        game_state.request_narration(Narration::Commentary(
+            CommentaryRequest::PlayerIntroduction {
+                players: list_of_players,
+            },
+        ));
+        Ok(())
+    }
+
+    fn next_question(game_state: &mut GameState, now: u64) -> Result<(), String> {
+        game_state.question = game_state
+            .game_questions
+            .get(game_state.question_number as usize - 1)
+            .cloned()
+            .ok_or("Question pool exhausted")?;
+        game_state.reveal = None;
+        game_state.review.clear();
+        game_state.question_start_time = now;
+        game_state.set_question_stage(QuestionStage::QuestionIntroduction, now);
+        game_state.question.answer_options = game_state.question.shuffled_answer_options();
+        Ok(())
+    }
+
+    /// Questions are picked when the game starts but only count as asked once shown, so
+    /// that an aborted game doesn't use up the rest of its questions
+    fn record_question_asked(game_state: &GameState, history: &Arc<Mutex<QuestionHistory>>) {
+        let mut history_mutex = match history.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+        history_mutex.record(&game_state.question.qualified_id(), unix_time_s());
+    }
+
+    fn announce_question(game_state: &mut GameState) {
+        game_state.request_narration(Narration::question(&game_state.question));
+    }
+
+    fn announce_answer(game_state: &mut GameState) {
+        game_state.request_narration(Narration::answer(&game_state.question));
+    }
+
+    /// Answer distribution and correct players for the current question
+    fn reveal_stats(game_state: &GameState, answers: &[Answers]) -> RevealStats {
+        let question_answers: Vec<&Answers> = answers
+            .iter()
+            .filter(|answer| answer.question_number == game_state.question_number)
+            .collect();
+
+        // Typed and ordered answers are counted per distinct answer, most common first
+        let options = match game_state.question.has_fixed_choices() {
+            true => game_state
+                .question
+                .answer_options
+                .clone()
+                .unwrap_or_default(),
+            false => {
+                let mut options: Vec<String> = vec![];
+                for answer in question_answers.iter() {
+                    if !options.contains(&answer.answer) {
+                        options.push(answer.answer.clone());
+                    }
+                }
+                options
             }
-            thread::sleep(Duration::from_millis(250));
+        };
+        let mut answer_counts: Vec<AnswerCount> = options
+            .into_iter()
+            .map(|option| AnswerCount {
+                count: question_answers
+                    .iter()
+                    .filter(|answer| answer.answer == option)
+                    .count() as u32,
+                answer: option,
+            })
+            .collect();
+        if game_state.question.answer_kind() == "numeric" {
+            // Estimates are ranked by how close they got
+            answer_counts.sort_by(|a, b| {
+                let a = game_state
+                    .question
+                    .distance(&a.answer)
+                    .unwrap_or(f64::INFINITY);
+                let b = game_state
+                    .question
+                    .distance(&b.answer)
+                    .unwrap_or(f64::INFINITY);
+                a.total_cmp(&b)
+            });
+        } else if !game_state.question.has_fixed_choices() {
+            answer_counts.sort_by_key(|answer_count| std::cmp::Reverse(answer_count.count));
+        }
+
+        let player_name = |uuid: &Uuid| {
+            game_state
+                .players
+                .iter()
+                .find(|player| player.uuid == *uuid)
+                .map(|player| player.name.clone())
+        };
+
+        let all_answers = answers_to_question(answers, game_state.question_number);
+        let mut correct_answers: Vec<&&Answers> = question_answers
+            .iter()
+            .filter(|answer| {
+                judge_answer(
+                    &game_state.question,
+                    &game_state.review,
+                    &answer.answer,
+                    &all_answers,
+                )
+            })
+            .collect();
+        correct_answers.sort_by_key(|answer| answer.time_ms);
+
+        // In team modes every member gets the result of the team's answer, captain mode
+        // players other than the captain never answer themselves
+        let player_results = game_state
+            .players
+            .iter()
+            .map(|player| PlayerResult {
+                player_uuid: player.uuid,
+                correct: match game_state.team_of(player.uuid) {
+                    Some(team) => team_answer(
+                        team,
+                        game_state.settings.team_mode,
+                        answers,
+                        game_state.question_number,
+                    )
+                    .is_some_and(|(answer, _)| {
+                        judge_answer(
+                            &game_state.question,
+                            &game_state.review,
+                            &answer,
+                            &all_answers,
+                        )
+                    }),
+                    None => correct_answers
+                        .iter()
+                        .any(|answer| answer.player_uuid == player.uuid),
+                },
+            })
+            .collect();
+
+        RevealStats {
+            answer_counts,
+            correct_players: correct_answers
+                .iter()
+                .filter_map(|answer| player_name(&answer.player_uuid))
+                .collect(),
+            fastest_player: correct_answers
+                .first()
+                .and_then(|answer| player_name(&answer.player_uuid)),
+            fastest_time_ms: correct_answers.first().map(|answer| answer.time_ms),
+            player_results,
+        }
+    }
+
+    fn announce_winner(game_state: &mut GameState) {
+        let winners: Vec<&Points> = game_state
+            .scores
+            .iter()
+            .filter(|points| points.position == 1)
+            .collect();
+        if let Some(winner) = winners.first() {
+            let winner_names: Vec<String> =
+                winners.iter().map(|points| points.name.clone()).collect();
+            let request = match (game_state.settings.team_mode, winners.len()) {
+                (TeamMode::Individual, 1) => CommentaryRequest::WinnerAnnouncement {
+                    winner: winner.name.clone(),
+                    points: winner.points,
+                },
+                (TeamMode::Individual, _) => CommentaryRequest::SharedWinAnnouncement {
+                    winners: winner_names,
+                    points: winner.points,
+                },
+                (_, 1) => CommentaryRequest::TeamWinnerAnnouncement {
+                    team: winner.name.clone(),
+                    members: winner.members.clone(),
+                    points: winner.points,
+                },
+                (_, _) => CommentaryRequest::SharedTeamWinAnnouncement {
+                    teams: winner_names,
+                    points: winner.points,
+                },
+            };
+            game_state.request_narration(Narration::Commentary(request));
         }
     }
 
-    fn count_players_answered_to_question(answers: &Vec<Answers>, question_number: u64) -> u64 {
+    fn count_players_answered_to_question(answers: &[Answers], question_number: u64) -> u64 {
         let mut num_answered: u64 = 0;
 
         for answer in answers.iter() {
@@ -371,135 +1351,606 @@ pub mod controller {
         num_answered
     }
 
-    fn shuffle_answers(question: &Question) -> Vec<String> {
-        let mut options: Vec<String> = vec![];
-        options.push(question.correct.clone());
-        options.push(question.incorrect_1.clone());
-        options.push(question.incorrect_2.clone());
-        options.push(question.incorrect_3.clone());
-        options.shuffle(&mut rand::rng());
-        options
+    /// Question IDs which must not be asked now: already asked in this game or too recently before
+    pub fn unavailable_question_ids(
+        questions: &[Questions],
+        history: &QuestionHistory,
+        settings: &GameSettings,
+    ) -> Vec<QuestionId> {
+        let mut ids = history.recently_asked(unix_time_s(), settings.repeat_after_days);
+        ids.extend(questions.iter().map(|asked| asked.question_id.clone()));
+        ids
+    }
+
+    /// Number of questions in the bank which pass category filters and can be asked
+    pub fn count_available_questions(
+        all_questions: &QuestionBank,
+        questions: &[Questions],
+        history: &QuestionHistory,
+        settings: &GameSettings,
+    ) -> u64 {
+        let unavailable = unavailable_question_ids(questions, history, settings);
+        available_questions(all_questions, &unavailable, settings).len() as u64
+    }
+
+    fn available_questions<'a>(
+        all_questions: &'a QuestionBank,
+        unavailable: &[QuestionId],
+        settings: &GameSettings,
+    ) -> Vec<&'a Question> {
+        all_questions
+            .questions
+            .iter()
+            .filter(|question| {
+                question_allowed(
+                    question,
+                    &settings.categories,
+                    &settings.excluded_categories,
+                )
+            })
+            .filter(|question| pack_allowed(question, &settings.packs))
+            .filter(|question| !unavailable.contains(&question.qualified_id()))
+            .collect()
     }
 
     fn get_new_question(
-        all_questions: &Root,
+        all_questions: &QuestionBank,
         questions: Arc<Mutex<Vec<Questions>>>,
+        history: &Arc<Mutex<QuestionHistory>>,
         question_number: u64,
-    ) -> Question {
-        let mut rng = rand::rng();
-
+        settings: &GameSettings,
+    ) -> Option<Question> {
         let mut questions_mutex = match questions.lock() {
             Ok(mutex) => mutex,
             Err(poisoned_mutex) => poisoned_mutex.into_inner(),
         };
 
-        //dbg!(&questions_mutex);
+        let history_mutex = match history.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
 
-        for _ in 0..all_questions.questions.len() {
-            let random_question = rng.random_range(0..all_questions.questions.len()) as i64;
-            let mut already_asked = false;
-            for asked in questions_mutex.iter() {
-                if asked.question_id == random_question {
-                    already_asked = true;
-                    break;
-                }
+        let unavailable = unavailable_question_ids(&questions_mutex, &history_mutex, settings);
+        let candidates = available_questions(all_questions, &unavailable, settings);
+
+        // Strategies continue from what the previous question of this game was picked for
+        let previous_category = match question_number {
+            1 => None,
+            _ => questions_mutex
+                .iter()
+                .find(|previous| previous.question_number == question_number - 1)
+                .and_then(|previous| previous.category.clone()),
+        };
+
+        let (question, category) = settings.selection.strategy().select(
+            &candidates,
+            question_number,
+            previous_category.as_deref(),
+        )?;
+
+        questions_mutex.push(Questions {
+            question_number,
+            question_id: question.qualified_id(),
+            category,
+        });
+        Some(question.clone())
+    }
+
+    /// Add points for the question that just closed to every player's score
+    fn score_question(game_state: &mut GameState, answers: &[Answers]) {
+        let scoring = game_state.settings.scoring.strategy();
+        let answer_time_ms = game_state.settings.answer_time_ms;
+        let all_answers = answers_to_question(answers, game_state.question_number);
+
+        for player in game_state.players.iter_mut() {
+            let answer = answers.iter().find(|answer| {
+                answer.player_uuid == player.uuid
+                    && answer.question_number == game_state.question_number
+            });
+            let correct = match answer {
+                Some(answer) => judge_answer(
+                    &game_state.question,
+                    &game_state.review,
+                    &answer.answer,
+                    &all_answers,
+                ),
+                None => false,
+            };
+            let time_ms = match answer {
+                Some(answer) => answer.time_ms,
+                None => answer_time_ms,
+            };
+            player.score += scoring.score(correct, time_ms, answer_time_ms, player.streak);
+            if correct {
+                player.correct_answers += 1;
+                player.streak += 1;
+            } else {
+                player.streak = 0;
             }
-            if !already_asked {
-                questions_mutex.push(Questions {
-                    question_number: question_number,
-                    question_id: random_question,
-                });
-                return all_questions.questions[random_question as usize].clone();
+        }
+
+        for team in game_state.teams.iter_mut() {
+            let answer = team_answer(
+                team,
+                game_state.settings.team_mode,
+                answers,
+                game_state.question_number,
+            );
+            let correct = match &answer {
+                Some((answer, _)) => judge_answer(
+                    &game_state.question,
+                    &game_state.review,
+                    answer,
+                    &all_answers,
+                ),
+                None => false,
+            };
+            let time_ms = match &answer {
+                Some((_, time_ms)) => *time_ms,
+                None => answer_time_ms,
+            };
+            team.score += scoring.score(correct, time_ms, answer_time_ms, team.streak);
+            if correct {
+                team.correct_answers += 1;
+                team.streak += 1;
+            } else {
+                team.streak = 0;
             }
         }
+    }
 
-        // Unable to find question which has not been asked -- return first
-        println!("PROBLEM! No more new questions!");
-        all_questions.questions[0].clone()
+    fn answers_to_question(answers: &[Answers], question_number: u64) -> Vec<&str> {
+        answers
+            .iter()
+            .filter(|answer| answer.question_number == question_number)
+            .map(|answer| answer.answer.as_str())
+            .collect()
     }
 
-    fn count_points(
-        all_questions: &Root,
-        game_state_mutex: &GameState,
-        questions: Arc<Mutex<Vec<Questions>>>,
-        answers: &Vec<Answers>,
-    ) -> Option<Vec<Points>> {
-        let mut result: Vec<Points> = vec![];
+    /// Group players by the team they registered with, keeping registration order
+    fn form_teams(game_state: &GameState) -> Vec<Team> {
+        let mut teams: Vec<Team> = vec![];
+        for player in game_state.players.iter() {
+            let key = match &player.team {
+                Some(team_name) => TeamKey::Registered(team_name.clone()),
+                None => TeamKey::Solo(player.uuid),
+            };
+            match teams.iter_mut().find(|team| team.key == key) {
+                Some(team) => team.members.push(player.uuid),
+                None => teams.push(Team {
+                    key,
+                    name: player.team.clone().unwrap_or(player.name.clone()),
+                    captain: player.uuid,
+                    members: vec![player.uuid],
+                    score: 0,
+                    correct_answers: 0,
+                    streak: 0,
+                }),
+            }
+        }
+        teams
+    }
 
-        let questions_mutex = match questions.lock() {
-            Ok(mutex) => mutex,
-            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
-        };
+    /// Answer the team gave and when it was given. In majority vote the most common
+    /// answer wins, a tie goes to the answer that got its first vote earliest. The team
+    /// has answered once the last member voting for the winning answer has.
+    fn team_answer(
+        team: &Team,
+        team_mode: TeamMode,
+        answers: &[Answers],
+        question_number: u64,
+    ) -> Option<(String, u64)> {
+        let team_answers: Vec<&Answers> = answers
+            .iter()
+            .filter(|answer| {
+                answer.question_number == question_number
+                    && team.members.contains(&answer.player_uuid)
+            })
+            .collect();
 
-        for player in game_state_mutex.players.iter() {
-            println!("Calculating points for {}", player.name);
-            let mut points: u32 = 0;
-            for answer in answers.iter() {
-                if answer.player_uuid == player.uuid {
-                    //println!("Question number: {}", answer.question_number);
-                    let correct_answer = get_correct_answer_for_question_id(
-                        get_question_id_for_question_number(
-                            answer.question_number,
-                            &questions_mutex,
-                        ),
-                        all_questions,
-                    );
-                    //println!("Answer is {} -- correct: {}", answer.answer, correct_answer);
-                    if answer.answer == correct_answer {
-                        //println!("Correct answer! {}", answer.answer);
-                        points += 1;
+        match team_mode {
+            TeamMode::Individual => None,
+            TeamMode::Captain => team_answers
+                .iter()
+                .find(|answer| answer.player_uuid == team.captain)
+                .map(|answer| (answer.answer.clone(), answer.time_ms)),
+            TeamMode::MajorityVote => {
+                // (answer, votes, first vote, last vote)
+                let mut votes: Vec<(String, u32, u64, u64)> = vec![];
+                for answer in team_answers.iter() {
+                    match votes.iter_mut().find(|vote| vote.0 == answer.answer) {
+                        Some(vote) => {
+                            vote.1 += 1;
+                            vote.2 = vote.2.min(answer.time_ms);
+                            vote.3 = vote.3.max(answer.time_ms);
+                        }
+                        None => {
+                            votes.push((answer.answer.clone(), 1, answer.time_ms, answer.time_ms))
+                        }
                     }
                 }
+                votes
+                    .into_iter()
+                    .min_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)))
+                    .map(|vote| (vote.0, vote.3))
             }
-            result.push(Points {
-                player_name: player.name.clone(),
-                points: points,
-            });
         }
-        sort_results_by_points(result)
     }
 
-    fn get_question_id_for_question_number(
-        question_number: u64,
-        questions: &Vec<Questions>,
-    ) -> i64 {
-        for question in questions.iter() {
-            if question.question_number == question_number {
-                //println!("{} --> {}", question_number, question.question_id);
-                return question.question_id;
+    /// Leaderboard of players or teams from current scores, compared against the
+    /// previous leaderboard
+    fn rank_players(game_state: &GameState) -> Vec<Points> {
+        let result = match game_state.settings.team_mode {
+            TeamMode::Individual => game_state
+                .players
+                .iter()
+                .map(|player| Points {
+                    player_uuid: Some(player.uuid),
+                    team: None,
+                    name: player.name.clone(),
+                    members: vec![],
+                    points: player.score,
+                    correct_answers: player.correct_answers,
+                    position: 0,
+                    position_change: 0,
+                })
+                .collect(),
+            _ => game_state
+                .teams
+                .iter()
+                .map(|team| Points {
+                    player_uuid: None,
+                    team: Some(team.key.clone()),
+                    name: team.name.clone(),
+                    members: game_state
+                        .players
+                        .iter()
+                        .filter(|player| team.members.contains(&player.uuid))
+                        .map(|player| player.name.clone())
+                        .collect(),
+                    points: team.score,
+                    correct_answers: team.correct_answers,
+                    position: 0,
+                    position_change: 0,
+                })
+                .collect(),
+        };
+        let mut result = sort_results_by_points(result);
+
+        for points in result.iter_mut() {
+            if let Some(previous) = game_state.scores.iter().find(|previous| {
+                previous.player_uuid == points.player_uuid && previous.team == points.team
+            }) {
+                points.position_change = previous.position as i32 - points.position as i32;
             }
         }
-        dbg!(questions);
-        return -999999;
+        result
     }
 
-    fn get_correct_answer_for_question_id(question_id: i64, all_questions: &Root) -> String {
-        for question in all_questions.questions.iter() {
-            if question.id == question_id {
-                return question.correct.clone();
+    /// Highest points first. Equal points share a position and the next position is
+    /// skipped (1, 1, 3), names decide display order within a tie.
+    fn sort_results_by_points(mut points: Vec<Points>) -> Vec<Points> {
+        points.sort_by(|a, b| b.points.cmp(&a.points).then_with(|| a.name.cmp(&b.name)));
+        let mut previous_points = None;
+        let mut position = 0;
+        for (index, player) in points.iter_mut().enumerate() {
+            if previous_points != Some(player.points) {
+                position = index as u32 + 1;
+                previous_points = Some(player.points);
             }
+            player.position = position;
         }
-        "-abcd".to_string()
+        points
     }
 
-    fn sort_results_by_points(input: Vec<Points>) -> Option<Vec<Points>> {
-        let mut result: Vec<Points> = vec![];
-        let mut max: u32 = 0;
-        let mut index = 0;
-        let mut points = input.clone();
-        dbg!(input);
-        for _n in 0..points.len() {
-            for (idx, player) in points.iter().enumerate() {
-                if player.points > max {
-                    max = player.points;
-                    index = idx;
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::game::state::Player;
+        use crate::questions::structure::QuestionKind;
+
+        const START: u64 = 1000;
+
+        fn test_question(id: i64) -> Question {
+            Question {
+                id,
+                pack: "testi".to_string(),
+                category: vec!["yleistieto".to_string()],
+                question: format!("Kysymys {}", id),
+                correct: "Oikea".to_string(),
+                kind: QuestionKind::MultipleChoice {
+                    correct: "Oikea".to_string(),
+                    incorrect: vec!["Väärä".to_string(), "Toinen väärä".to_string()],
+                },
+                ..Default::default()
+            }
+        }
+
+        fn test_player(name: &str) -> Player {
+            Player {
+                name: name.to_string(),
+                uuid: Uuid::new_v4(),
+                last_seen: 0,
+                score: 0,
+                correct_answers: 0,
+                streak: 0,
+                team: None,
+            }
+        }
+
+        /// Game with its own history file so that tests don't see each other's questions
+        struct TestGame {
+            game_state: GameState,
+            questions: Arc<Mutex<Vec<Questions>>>,
+            history: Arc<Mutex<QuestionHistory>>,
+            answers: Vec<Answers>,
+            all_questions: QuestionBank,
+        }
+
+        impl TestGame {
+            fn new(name: &str, players: &[&str], question_limit: u64) -> TestGame {
+                let path = std::env::temp_dir().join(format!(
+                    "pubiq-test-{}-{}.json",
+                    name,
+                    std::process::id()
+                ));
+                TestGame {
+                    game_state: GameState {
+                        settings: GameSettings {
+                            question_limit,
+                            ..Default::default()
+                        },
+                        players: players.iter().map(|name| test_player(name)).collect(),
+                        ..Default::default()
+                    },
+                    questions: Arc::new(Mutex::new(vec![])),
+                    history: Arc::new(Mutex::new(QuestionHistory {
+                        entries: vec![],
+                        path: path.display().to_string(),
+                    })),
+                    answers: vec![],
+                    all_questions: QuestionBank {
+                        packs: vec![],
+                        questions: (0..10).map(test_question).collect(),
+                    },
                 }
             }
-            result.push(points.get(index)?.clone());
-            points.remove(index);
-            max = 0;
+
+            fn handle(&mut self, event: GameEvent, now: u64) {
+                handle_event(
+                    &mut self.game_state,
+                    &self.questions,
+                    &self.history,
+                    &mut self.answers,
+                    &self.all_questions,
+                    event,
+                    now,
+                );
+            }
+
+            /// Start the game and show the first question at `START`
+            fn start(&mut self) {
+                self.handle(GameEvent::Proceed, START);
+                self.handle(GameEvent::Proceed, START);
+            }
+
+            fn answer(&mut self, player: usize, answer: &str, now: u64) {
+                self.answers.push(Answers {
+                    question_number: self.game_state.question_number,
+                    answer: answer.to_string(),
+                    player_uuid: self.game_state.players[player].uuid,
+                    time_ms: 0,
+                });
+                self.handle(GameEvent::AnswerSubmitted, now);
+            }
+
+            fn in_stage(&self, question_stage: QuestionStage) -> bool {
+                self.game_state.game_stage == GameStage::GameInProgress
+                    && self.game_state.question_stage == question_stage
+            }
+        }
+
+        impl Drop for TestGame {
+            fn drop(&mut self) {
+                let history_mutex = match self.history.lock() {
+                    Ok(mutex) => mutex,
+                    Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+                };
+                let _ = std::fs::remove_file(&history_mutex.path);
+            }
+        }
+
+        #[test]
+        fn proceed_starts_game_and_shows_first_question() {
+            let mut game = TestGame::new("proceed", &["Anna", "Bertta"], 3);
+            game.handle(GameEvent::Proceed, START);
+            assert!(game.game_state.game_stage == GameStage::IntroducePlayers);
+            assert_eq!(game.game_state.game_questions.len(), 3);
+            assert_eq!(game.game_state.question_number, 1);
+
+            game.handle(GameEvent::Proceed, START + 500);
+            assert!(game.in_stage(QuestionStage::QuestionIntroduction));
+            assert_eq!(game.game_state.stage_start_time, START + 500);
+
+            game.handle(GameEvent::Proceed, START + 600);
+            assert!(game.in_stage(QuestionStage::QuestionAnswerTime));
+            assert_eq!(game.game_state.stage_start_time, START + 600);
+        }
+
+        #[test]
+        fn proceed_does_not_end_answer_time() {
+            let mut game = TestGame::new("answer-time", &["Anna"], 3);
+            game.start();
+            game.handle(GameEvent::Proceed, START);
+            game.handle(GameEvent::Proceed, START + 100);
+            assert!(game.in_stage(QuestionStage::QuestionAnswerTime));
+        }
+
+        #[test]
+        fn early_deadline_event_is_ignored() {
+            let mut game = TestGame::new("deadline", &["Anna"], 3);
+            game.start();
+            let deadline = START + game.game_state.settings.introduction_time_ms;
+            assert_eq!(game.game_state.stage_deadline(), Some(deadline));
+
+            game.handle(GameEvent::Deadline, deadline - 1);
+            assert!(game.in_stage(QuestionStage::QuestionIntroduction));
+
+            game.handle(GameEvent::Deadline, deadline);
+            assert!(game.in_stage(QuestionStage::QuestionAnswerTime));
+            assert_eq!(game.game_state.stage_start_time, deadline);
+        }
+
+        #[test]
+        fn answer_time_ends_at_deadline() {
+            let mut game = TestGame::new("answer-deadline", &["Anna", "Bertta"], 3);
+            game.start();
+            game.handle(GameEvent::Proceed, START);
+            game.answer(0, "Oikea", START + 100);
+            assert!(game.in_stage(QuestionStage::QuestionAnswerTime));
+
+            let deadline = START + game.game_state.settings.answer_time_ms;
+            game.handle(GameEvent::Deadline, deadline);
+            assert!(game.in_stage(QuestionStage::QuestionFinished));
+            assert_eq!(game.game_state.players[0].score, 1);
+            assert_eq!(game.game_state.players[1].score, 0);
+        }
+
+        #[test]
+        fn answer_time_ends_early_when_everyone_has_answered() {
+            let mut game = TestGame::new("all-answered", &["Anna", "Bertta"], 3);
+            game.start();
+            game.handle(GameEvent::Proceed, START);
+            game.answer(0, "Oikea", START + 100);
+            assert!(game.in_stage(QuestionStage::QuestionAnswerTime));
+
+            game.answer(1, "Väärä", START + 200);
+            assert!(game.in_stage(QuestionStage::QuestionFinished));
+            assert_eq!(game.game_state.stage_start_time, START + 200);
+            let reveal = game.game_state.reveal.as_ref().unwrap();
+            assert_eq!(reveal.correct_players, vec!["Anna".to_string()]);
+        }
+
+        #[test]
+        fn game_ends_after_last_question_and_new_game_keeps_settings() {
+            let mut game = TestGame::new("game-end", &["Anna"], 2);
+            game.start();
+            let mut now = START;
+            for question_number in 1..=2 {
+                assert_eq!(game.game_state.question_number, question_number);
+                game.handle(GameEvent::Proceed, now);
+                game.answer(0, "Oikea", now);
+                assert!(game.in_stage(QuestionStage::QuestionFinished));
+                now += 100;
+                game.handle(GameEvent::Proceed, now);
+            }
+            assert!(game.game_state.game_stage == GameStage::ResultsShow);
+            assert_eq!(game.game_state.players[0].score, 2);
+
+            game.handle(GameEvent::NewGame, now);
+            assert!(game.game_state.game_stage == GameStage::WaitingForPlayers);
+            assert_eq!(game.game_state.settings.question_limit, 2);
+        }
+
+        #[test]
+        fn captain_answer_decides_result_of_whole_team() {
+            let mut game = TestGame::new("captain", &["Anna", "Bertta", "Cecilia"], 3);
+            game.game_state.settings.team_mode = TeamMode::Captain;
+            game.game_state.players[0].team = Some("Kettu".to_string());
+            game.game_state.players[1].team = Some("Kettu".to_string());
+            game.start();
+            game.handle(GameEvent::Proceed, START);
+            assert!(!game.game_state.may_answer(game.game_state.players[1].uuid));
+
+            game.answer(0, "Oikea", START + 100);
+            game.answer(2, "Väärä", START + 200);
+            assert!(game.in_stage(QuestionStage::QuestionFinished));
+            let reveal = game.game_state.reveal.as_ref().unwrap();
+            let correct: Vec<bool> = reveal
+                .player_results
+                .iter()
+                .map(|result| result.correct)
+                .collect();
+            assert_eq!(correct, vec![true, true, false]);
+        }
+
+        fn points(name: &str, points: u32) -> Points {
+            Points {
+                player_uuid: None,
+                team: None,
+                name: name.to_string(),
+                members: vec![],
+                points,
+                correct_answers: 0,
+                position: 0,
+                position_change: 0,
+            }
+        }
+
+        #[test]
+        fn equal_points_share_position() {
+            let ranked = sort_results_by_points(vec![
+                points("Cecilia", 2),
+                points("Bertta", 3),
+                points("Anna", 3),
+                points("Daniel", 1),
+            ]);
+            let ranked: Vec<(&str, u32)> = ranked
+                .iter()
+                .map(|points| (points.name.as_str(), points.position))
+                .collect();
+            assert_eq!(
+                ranked,
+                vec![("Anna", 1), ("Bertta", 1), ("Cecilia", 3), ("Daniel", 4)]
+            );
+        }
+
+        #[test]
+        fn position_change_is_compared_to_previous_leaderboard() {
+            let mut game = TestGame::new("ranking", &["Anna", "Bertta"], 3);
+            game.game_state.players[1].score = 1;
+            game.game_state.scores = rank_players(&game.game_state);
+            assert_eq!(game.game_state.scores[0].name, "Bertta");
+
+            game.game_state.players[0].score = 2;
+            let ranked = rank_players(&game.game_state);
+            assert_eq!(ranked[0].name, "Anna");
+            assert_eq!(ranked[0].position_change, 1);
+            assert_eq!(ranked[1].position_change, -1);
+        }
+
+        #[test]
+        fn solo_player_is_not_merged_with_team_of_same_name() {
+            let mut game = TestGame::new("solo-team", &["Anna", "Bertta", "Kettu"], 3);
+            game.game_state.players[0].team = Some("Kettu".to_string());
+            game.game_state.players[1].team = Some("Kettu".to_string());
+            let teams = form_teams(&game.game_state);
+            assert_eq!(teams.len(), 2);
+            assert_eq!(teams[0].members.len(), 2);
+            assert_eq!(teams[1].name, "Kettu");
+            assert_eq!(teams[1].members, vec![game.game_state.players[2].uuid]);
+            assert_eq!(teams[1].captain, game.game_state.players[2].uuid);
+        }
+
+        #[test]
+        fn teams_are_ranked_with_their_members() {
+            let mut game = TestGame::new("team-ranking", &["Anna", "Bertta", "Cecilia"], 3);
+            game.game_state.settings.team_mode = TeamMode::MajorityVote;
+            game.game_state.players[0].team = Some("Kettu".to_string());
+            game.game_state.players[1].team = Some("Kettu".to_string());
+            game.game_state.teams = form_teams(&game.game_state);
+            for team in game.game_state.teams.iter_mut() {
+                team.score = 1;
+            }
+
+            let ranked = rank_players(&game.game_state);
+            assert_eq!(ranked.len(), 2);
+            assert!(ranked.iter().all(|points| points.position == 1));
+            let kettu = ranked.iter().find(|points| points.name == "Kettu").unwrap();
+            assert_eq!(
+                kettu.members,
+                vec!["Anna".to_string(), "Bertta".to_string()]
+            );
         }
-        dbg!(&result);
-        Some(result)
     }
 }
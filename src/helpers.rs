@@ -23,6 +23,14 @@ pub mod time_helpers {
             }
         }
     }
+
+    /// Return wall clock time as Unix seconds, for timestamps that outlive the process
+    pub fn unix_time_s() -> u64 {
+        match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs(),
+            Err(_) => 0,
+        }
+    }
 }
 
 pub mod natural_language {
@@ -60,4 +68,69 @@ pub mod natural_language {
     pub fn prompt_for_winner_announcement(winner: String, num_points: String) -> String {
         format!("Olet tietovisaisäntä. Tietovisa on juuri päättynyt ja voittajaksi on selviytynyt pelaaja nimeltä {}. Hän keräsi {} pistettä! Onnittele voittajaa sekä kiitä kaikkia osallistujia pelistä. Käytä ylitsevuotavaista hehkutusta, jos mahdollista. Vastaus voi olla enintään neljä lausetta pitkä.", winner, num_points)
     }
+
+    pub fn prompt_for_shared_win_announcement(winners: String, num_points: String) -> String {
+        format!("Olet tietovisaisäntä. Tietovisa on juuri päättynyt tasapeliin ja voiton jakavat pelaajat nimeltä {}. He keräsivät kukin {} pistettä! Onnittele voittajia sekä kiitä kaikkia osallistujia pelistä. Käytä ylitsevuotavaista hehkutusta, jos mahdollista. Vastaus voi olla enintään neljä lausetta pitkä.", winners, num_points)
+    }
+
+    pub fn prompt_for_team_winner_announcement(
+        team: String,
+        members: String,
+        num_points: String,
+    ) -> String {
+        format!("Olet tietovisaisäntä. Tietovisa on juuri päättynyt ja voittajaksi on selviytynyt joukkue nimeltä {}. Joukkueeseen kuuluvat {}. Joukkue keräsi {} pistettä! Onnittele voittajajoukkuetta sekä kiitä kaikkia osallistujia pelistä. Käytä ylitsevuotavaista hehkutusta, jos mahdollista. Vastaus voi olla enintään neljä lausetta pitkä.", team, members, num_points)
+    }
+
+    pub fn prompt_for_shared_team_win_announcement(teams: String, num_points: String) -> String {
+        format!("Olet tietovisaisäntä. Tietovisa on juuri päättynyt tasapeliin ja voiton jakavat joukkueet nimeltä {}. Ne keräsivät kukin {} pistettä! Onnittele voittajajoukkueita sekä kiitä kaikkia osallistujia pelistä. Käytä ylitsevuotavaista hehkutusta, jos mahdollista. Vastaus voi olla enintään neljä lausetta pitkä.", teams, num_points)
+    }
+
+    pub fn template_for_player_introduction(players: String) -> String {
+        format!(
+            "Tervetuloa Pub I Q -tietovisaan! Tänään kisaavat {}. Onnea kaikille, ja aloitetaan!",
+            players
+        )
+    }
+
+    pub fn template_for_winner_announcement(winner: String, num_points: String) -> String {
+        format!("Tietovisa on päättynyt, ja voittaja on {} {} pisteellä! Onnittelut voittajalle, ja kiitos kaikille osallistujille!", winner, num_points)
+    }
+
+    pub fn template_for_shared_win_announcement(winners: String, num_points: String) -> String {
+        format!("Tietovisa päättyi tasapeliin! Voiton jakavat {}, kukin {} pisteellä. Onnittelut voittajille, ja kiitos kaikille osallistujille!", winners, num_points)
+    }
+
+    pub fn template_for_team_winner_announcement(
+        team: String,
+        members: String,
+        num_points: String,
+    ) -> String {
+        format!("Tietovisa on päättynyt, ja voittajajoukkue on {}! Joukkueessa pelasivat {}, ja se keräsi {} pistettä. Onnittelut voittajille, ja kiitos kaikille osallistujille!", team, members, num_points)
+    }
+
+    pub fn template_for_shared_team_win_announcement(teams: String, num_points: String) -> String {
+        format!("Tietovisa päättyi tasapeliin! Voiton jakavat joukkueet {}, kukin {} pisteellä. Onnittelut voittajille, ja kiitos kaikille osallistujille!", teams, num_points)
+    }
+}
+
+pub mod secrets {
+    use uuid::Uuid;
+
+    /// Random token which is only known to whoever created it
+    pub fn generate_secret() -> String {
+        Uuid::new_v4().simple().to_string()
+    }
+
+    /// Compare without returning early, so that response time does not tell how much
+    /// of the secret was guessed right
+    pub fn secrets_match(expected: &str, given: &str) -> bool {
+        if expected.len() != given.len() {
+            return false;
+        }
+        expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+    }
 }
@@ -6,92 +6,200 @@ mod questions;
 /// https://github.com/mr-entropia
 /// Licensed under AGPL-3.0
 mod rest_api;
+mod rooms;
 
+use external_apis::host::Host;
 use game::{
-    controller::run_game_controller,
-    state::{Answers, GameState, Questions},
+    narration::{clean_speech_cache, prepare_narrations, question_narrations},
+    settings::load_settings_from_file,
+};
+use questions::{
+    bank::{watch_question_packs, BankHandle},
+    history::load_history_from_file,
+    importer::import_file,
+    loader::load_question_packs,
+    selection::pack_allowed,
+    spreadsheet::export_file,
+    structure::Question,
 };
-use questions::{loader::load_questions_from_file, structure::Question};
 use rest_api::rest_http::run_rest_http_api;
+use rooms::registry::{run_room_reaper, RoomRegistry};
 use std::{
-    sync::{Arc, Mutex},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
     thread,
     time::Duration,
-    vec,
 };
 
-fn main() {
-    let all_questions: &'static questions::structure::Root =
-        match load_questions_from_file("questions.json") {
-            Some(questions) => Box::leak(Box::new(questions)),
-            None => {
-                eprintln!("Unable to load questions. Exiting.");
-                std::process::exit(-1);
+const QUESTION_PACK_DIR: &str = "questions";
+
+/// `pubiq import [--replace] <file> <pack>` and `pubiq export <pack> <spreadsheet>`
+/// convert between question packs and CSV/TSV, Open Trivia DB and GIFT files. Returns
+/// process exit code.
+fn run_spreadsheet_command(args: &[String]) -> i32 {
+    let (command, replace, from, to) = match args {
+        [_, command, from, to] => (command.as_str(), false, Path::new(from), Path::new(to)),
+        [_, command, option, from, to] if command == "import" && option == "--replace" => {
+            (command.as_str(), true, Path::new(from), Path::new(to))
+        }
+        _ => {
+            eprintln!("Usage: pubiq import [--replace] <questions.csv|.tsv|.json|.gift> <questions/pack.json>");
+            eprintln!("       pubiq export <questions/pack.json> <questions.csv|.tsv>");
+            return 2;
+        }
+    };
+    let result = match command {
+        "import" => import_file(from, to, replace),
+        _ => export_file(from, to),
+    };
+    match result {
+        Ok(message) => {
+            println!("{}", message);
+            0
+        }
+        Err(problems) => {
+            for problem in problems.iter() {
+                eprintln!("{}", problem);
             }
-        };
-
-    let game_state = Arc::new(Mutex::new(GameState {
-        game_stage: game::state::GameStage::WaitingForPlayers,
-        question: Question {
-            id: 0,
-            category: vec![],
-            question: "".to_string(),
-            question_tts: "".to_string(),
-            context_information: "".to_string(),
-            context_information_tts: "".to_string(),
-            correct: "".to_string(),
-            correct_tts: "".to_string(),
-            incorrect_1: "".to_string(),
-            incorrect_2: "".to_string(),
-            incorrect_3: "".to_string(),
-            answer_options: None,
-        },
-        question_number: 0,
-        question_stage: game::state::QuestionStage::QuestionIntroduction,
-        question_start_time: 0,
-        question_limit: 5,
-        players: vec![],
-        proceed_flag: false,
-        newgame_flag: false,
-        audio: None,
-        tts_text: None,
-        scores: vec![],
-    }));
-
-    let empty_questions: Vec<Questions> = vec![];
-    let empty_answers: Vec<Answers> = vec![];
-
-    let questions = Arc::new(Mutex::new(empty_questions));
-    let answers = Arc::new(Mutex::new(empty_answers));
-
-    let game_state_clone = game_state.clone();
-    let questions_clone = questions.clone();
-    let answers_clone = answers.clone();
+            eprintln!("Nothing was written.");
+            1
+        }
+    }
+}
 
-    // Start REST API
-    let builder = thread::Builder::new().name("REST API".into());
-    match builder.spawn(move || {
-        run_rest_http_api(game_state_clone, questions_clone, answers_clone);
-    }) {
-        Ok(_) => (),
-        Err(_) => {
+/// `pubiq warm-cache [pack ...]` generates audio for every question and answer in the given
+/// packs, or all of them, so that the speech engine is not waited on during a quiz night.
+/// Returns process exit code.
+fn run_warm_cache_command(packs: &[String]) -> i32 {
+    let bank = match load_question_packs(QUESTION_PACK_DIR) {
+        Ok(bank) => bank,
+        Err(problems) => {
+            for problem in problems.iter() {
+                eprintln!("{}", problem);
+            }
+            eprintln!("Unable to load questions.");
+            return 1;
+        }
+    };
+    if let Some(unknown) = packs
+        .iter()
+        .find(|pack| !bank.packs.iter().any(|known| &known.name == *pack))
+    {
+        eprintln!("Unknown question pack {}", unknown);
+        return 2;
+    }
+
+    let bank_questions = bank.questions;
+    let questions: Vec<Question> = bank_questions
+        .iter()
+        .filter(|question| pack_allowed(question, packs))
+        .cloned()
+        .collect();
+    let host = Host::from_env();
+    if host.speech.name() == "silent" {
+        eprintln!("Speech is turned off, choose a speech engine with PUBIQ_TTS.");
+        return 1;
+    }
+    let narrations = question_narrations(&questions);
+    let total = narrations.len();
+    let done = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    prepare_narrations(&host, narrations, &|audio| {
+        if !audio {
+            failed.fetch_add(1, Ordering::Relaxed);
+        }
+        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+        println!("{}/{} done", done, total);
+    });
+    // No cleanup here, the server may be using the cache at the same time
+
+    let failed = failed.load(Ordering::Relaxed);
+    match failed {
+        0 => {
+            println!("Audio of {} questions is ready.", questions.len());
+            0
+        }
+        _ => {
+            eprintln!("No audio for {} of {} texts.", failed, total);
+            1
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|command| command.as_str()) {
+        Some("import" | "export") => std::process::exit(run_spreadsheet_command(&args)),
+        Some("warm-cache") => std::process::exit(run_warm_cache_command(&args[2..])),
+        _ => (),
+    }
+
+    let question_bank = match load_question_packs(QUESTION_PACK_DIR) {
+        Ok(bank) => Arc::new(BankHandle::new(QUESTION_PACK_DIR, bank)),
+        Err(problems) => {
+            for problem in problems.iter() {
+                eprintln!("{}", problem);
+            }
+            eprintln!("Unable to load questions. Exiting.");
             std::process::exit(-1);
         }
+    };
+
+    // Edited packs are picked up without restarting, games in progress are not affected
+    if let Err(error) = watch_question_packs(question_bank.clone()) {
+        eprintln!("Unable to watch question packs for changes: {}", error);
     }
 
-    let game_state_clone = game_state.clone();
-    let questions_clone = questions.clone();
-    let answers_clone = answers.clone();
+    let settings = match load_settings_from_file("settings.json") {
+        Some(settings) => settings,
+        None => {
+            eprintln!("Unable to load settings. Exiting.");
+            std::process::exit(-1);
+        }
+    };
+
+    let history = match load_history_from_file("question_history.json") {
+        Some(history) => Arc::new(Mutex::new(history)),
+        None => {
+            eprintln!("Unable to load question history. Exiting.");
+            std::process::exit(-1);
+        }
+    };
+
+    let host = Host::from_env();
+    // Audio of edited and removed questions is not needed anymore
+    clean_speech_cache(&host, &question_bank.current().questions);
+
+    // Game controllers signal state changes of their room, REST API pushes them to clients
+    let (state_changed_tx, state_changed_rx) = mpsc::channel::<String>();
 
-    // Start game controller
-    let builder = thread::Builder::new().name("Game controller".into());
+    let rooms = Arc::new(RoomRegistry::new(
+        question_bank,
+        host,
+        history,
+        settings,
+        state_changed_tx,
+    ));
+
+    // Close rooms which presenters have abandoned
+    let rooms_clone = rooms.clone();
+    let builder = thread::Builder::new().name("Room reaper".into());
+    if builder
+        .spawn(move || {
+            run_room_reaper(rooms_clone);
+        })
+        .is_err()
+    {
+        eprintln!("Unable to start room reaper, idle rooms stay open");
+    }
+
+    // Start REST API
+    let builder = thread::Builder::new().name("REST API".into());
     match builder.spawn(move || {
-        run_game_controller(
-            game_state_clone,
-            questions_clone,
-            answers_clone,
-            all_questions,
-        );
+        run_rest_http_api(rooms, state_changed_rx);
     }) {
         Ok(_) => (),
         Err(_) => {
@@ -1,36 +1,283 @@
 pub mod loader {
-    use super::structure::Root;
+    use super::media::{validate_media, MEDIA_DIR};
+    use super::structure::{Metadata, Pack, QuestionBank, Root};
+    use super::validation::{parse_questions, validate_questions};
+    use serde::Deserialize;
+    use std::path::Path;
 
-    pub fn load_questions_from_file(path: &str) -> Option<Root> {
+    /// Pack file read before its questions are checked one by one
+    #[derive(Deserialize)]
+    struct RawPack {
+        metadata: Metadata,
+        questions: Vec<serde_json::Value>,
+    }
+
+    /// Load every `.json` pack in a directory. Pack name is the file name without extension.
+    /// Returns every problem found in any pack if even one pack can't be used.
+    pub fn load_question_packs(dir: &str) -> Result<QuestionBank, Vec<String>> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_e) => {
+                return Err(vec![format!(
+                    "Question pack directory ({}) was not found.",
+                    dir
+                )]);
+            }
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect();
+        paths.sort();
+
+        if paths.is_empty() {
+            return Err(vec![format!(
+                "Question pack directory ({}) has no packs.",
+                dir
+            )]);
+        }
+
+        let mut bank = QuestionBank::default();
+        let mut problems: Vec<String> = vec![];
+        for path in paths.iter() {
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            match load_questions_from_file(path, &name) {
+                Ok(pack) => {
+                    println!(
+                        "Loaded question pack {} ({} questions)",
+                        name,
+                        pack.questions.len()
+                    );
+                    bank.questions.extend(pack.questions);
+                    bank.packs.push(Pack {
+                        name,
+                        metadata: pack.metadata,
+                    });
+                }
+                Err(pack_problems) => problems.extend(pack_problems),
+            }
+        }
+
+        match problems.is_empty() {
+            true => Ok(bank),
+            false => Err(problems),
+        }
+    }
+
+    pub fn load_questions_from_file(path: &Path, pack_name: &str) -> Result<Root, Vec<String>> {
         let in_file = std::fs::read_to_string(path);
+        let path = path.display();
 
         let data = match in_file {
             Ok(s) => s,
             Err(_e) => {
-                eprintln!("Questions file ({}) was not found.", path);
-                return None;
+                return Err(vec![format!("Questions file ({}) was not found.", path)]);
             }
         };
 
-        match serde_json::from_str(&data) {
-            Ok(val) => {
-                return Some(val);
+        match serde_json::from_str::<RawPack>(&data) {
+            Ok(raw) => {
+                let (questions, mut problems) = parse_questions(raw.questions, pack_name);
+                let val = Root {
+                    metadata: raw.metadata,
+                    questions,
+                };
+                problems.extend(validate_questions(&val));
+                problems.extend(validate_media(&val, MEDIA_DIR));
+                if !problems.is_empty() {
+                    let mut report = vec![format!(
+                        "Questions file ({}) has {} problems:",
+                        path,
+                        problems.len()
+                    )];
+                    report.extend(problems.iter().map(|problem| format!("  {}", problem)));
+                    return Err(report);
+                }
+                Ok(val)
             }
-            Err(e) => {
-                eprintln!(
-                    "Questions file ({}) is invalid.\n\nError: {}",
-                    path,
-                    e.to_string()
-                );
-                return None;
+            Err(e) => Err(vec![format!(
+                "Questions file ({}) is invalid.\n\nError: {}",
+                path, e
+            )]),
+        }
+    }
+}
+
+pub mod bank {
+    use super::loader::load_question_packs;
+    use super::structure::QuestionBank;
+    #[cfg(target_os = "linux")]
+    use nix::errno::Errno;
+    #[cfg(target_os = "linux")]
+    use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    /// How often pack directory is checked for changes. Editors often write a file in
+    /// several steps, so changes are collected for a while before reloading.
+    const WATCH_INTERVAL_MS: u64 = 1000;
+
+    /// Currently loaded question bank. Games keep using the bank they started with,
+    /// a reload is picked up by rooms when their next game starts.
+    pub struct BankHandle {
+        dir: String,
+        current: Mutex<Arc<QuestionBank>>,
+    }
+
+    impl BankHandle {
+        pub fn new(dir: &str, bank: QuestionBank) -> BankHandle {
+            BankHandle {
+                dir: dir.to_string(),
+                current: Mutex::new(Arc::new(bank)),
+            }
+        }
+
+        pub fn current(&self) -> Arc<QuestionBank> {
+            let current_mutex = match self.current.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+            current_mutex.clone()
+        }
+
+        /// Load packs again. On problems the bank in use is kept and problems are returned.
+        pub fn reload(&self) -> Result<Arc<QuestionBank>, Vec<String>> {
+            let bank = Arc::new(load_question_packs(&self.dir)?);
+            let mut current_mutex = match self.current.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+            *current_mutex = bank.clone();
+            println!(
+                "Question bank reloaded: {} packs, {} questions",
+                bank.packs.len(),
+                bank.questions.len()
+            );
+            Ok(bank)
+        }
+    }
+
+    /// Reload the bank whenever a pack is written, added, renamed or removed (Linux)
+    #[cfg(target_os = "linux")]
+    pub fn watch_question_packs(handle: Arc<BankHandle>) -> Result<(), String> {
+        let inotify = match Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC) {
+            Ok(inotify) => inotify,
+            Err(error) => return Err(format!("{}", error)),
+        };
+        let flags = AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_MOVED_TO
+            | AddWatchFlags::IN_MOVED_FROM
+            | AddWatchFlags::IN_DELETE;
+        if let Err(error) = inotify.add_watch(handle.dir.as_str(), flags) {
+            return Err(format!("{}", error));
+        }
+
+        let builder = thread::Builder::new().name("Question pack watcher".into());
+        let result = builder.spawn(move || loop {
+            thread::sleep(Duration::from_millis(WATCH_INTERVAL_MS));
+            let mut changed = false;
+            loop {
+                match inotify.read_events() {
+                    Ok(events) => {
+                        changed |= events.iter().any(|event| {
+                            event
+                                .name
+                                .as_ref()
+                                .is_some_and(|name| name.to_string_lossy().ends_with(".json"))
+                        })
+                    }
+                    Err(Errno::EAGAIN) => break,
+                    Err(error) => {
+                        eprintln!("Unable to watch question packs: {}", error);
+                        return;
+                    }
+                }
             }
+            if changed {
+                if let Err(problems) = handle.reload() {
+                    eprintln!("Question packs changed but were not reloaded:");
+                    for problem in problems.iter() {
+                        eprintln!("{}", problem);
+                    }
+                }
+            }
+        });
+        match result {
+            Ok(_) => Ok(()),
+            Err(error) => Err(format!("{}", error)),
+        }
+    }
+
+    /// Pack files with their modification times and sizes
+    #[cfg(not(target_os = "linux"))]
+    fn pack_files(dir: &str) -> Result<Vec<(String, std::time::SystemTime, u64)>, String> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) => return Err(format!("{}", error)),
         };
+        let mut files = vec![];
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.ends_with(".json") {
+                continue;
+            }
+            if let Ok(metadata) = entry.metadata() {
+                let modified = metadata.modified().unwrap_or(std::time::UNIX_EPOCH);
+                files.push((name, modified, metadata.len()));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Reload the bank whenever a pack is written, added, renamed or removed (other
+    /// systems, which are polled for modification times)
+    #[cfg(not(target_os = "linux"))]
+    pub fn watch_question_packs(handle: Arc<BankHandle>) -> Result<(), String> {
+        let mut previous = pack_files(&handle.dir)?;
+
+        let builder = thread::Builder::new().name("Question pack watcher".into());
+        let result = builder.spawn(move || loop {
+            thread::sleep(Duration::from_millis(WATCH_INTERVAL_MS));
+            let files = match pack_files(&handle.dir) {
+                Ok(files) => files,
+                Err(error) => {
+                    eprintln!("Unable to watch question packs: {}", error);
+                    return;
+                }
+            };
+            if files != previous {
+                previous = files;
+                if let Err(problems) = handle.reload() {
+                    eprintln!("Question packs changed but were not reloaded:");
+                    for problem in problems.iter() {
+                        eprintln!("{}", problem);
+                    }
+                }
+            }
+        });
+        match result {
+            Ok(_) => Ok(()),
+            Err(error) => Err(format!("{}", error)),
+        }
     }
 }
 
 pub mod structure {
     use serde::{Deserialize, Serialize};
 
+    /// One question pack file
     #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
     #[serde(rename_all = "kebab-case")]
     pub struct Root {
@@ -43,29 +290,2936 @@ pub mod structure {
     pub struct Metadata {
         pub author: String,
         pub time: i64,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        pub title: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        pub language: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        pub difficulty: String,
+        /// Categories questions may use. Empty list allows any category.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub categories: Vec<String>,
+    }
+
+    /// All loaded packs with their questions merged into one list
+    #[derive(Default, Debug, Clone, PartialEq)]
+    pub struct QuestionBank {
+        pub packs: Vec<Pack>,
+        pub questions: Vec<Question>,
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq, Serialize)]
+    pub struct Pack {
+        /// File name without extension, used as namespace of question IDs
+        pub name: String,
+        pub metadata: Metadata,
+    }
+
+    /// Question ID is only unique within its pack
+    #[derive(
+        Default, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+    )]
+    pub struct QuestionId {
+        pub pack: String,
+        pub id: i64,
+    }
+
+    impl std::fmt::Display for QuestionId {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}/{}", self.pack, self.id)
+        }
     }
 
     #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-    #[serde(rename_all = "kebab-case")]
+    #[serde(try_from = "RawQuestion", into = "RawQuestion")]
     pub struct Question {
         pub id: i64,
+        /// Pack the question was loaded from, set by loader
+        pub pack: String,
         pub category: Vec<String>,
         pub question: String,
-        #[serde(rename = "question_tts")]
         pub question_tts: String,
-        #[serde(rename = "context_information")]
         pub context_information: String,
-        #[serde(rename = "context_information_tts")]
         pub context_information_tts: String,
+        /// Correct answer as shown on presenter's screen
         pub correct: String,
-        #[serde(rename = "correct_tts")]
         pub correct_tts: String,
-        #[serde(rename = "incorrect_1")]
-        pub incorrect_1: String,
-        #[serde(rename = "incorrect_2")]
-        pub incorrect_2: String,
-        #[serde(rename = "incorrect_3")]
-        pub incorrect_3: String,
+        pub kind: QuestionKind,
+        pub media: Media,
+        /// Options shown to players in the current game, set when question is asked
         pub answer_options: Option<Vec<String>>,
     }
+
+    /// Pictures and clips shown on presenter's screen with the question. Files are
+    /// relative to the media directory, video may also be an http(s) URL.
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Media {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub image: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub audio: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub video: Option<String>,
+    }
+
+    impl Question {
+        pub fn qualified_id(&self) -> QuestionId {
+            QuestionId {
+                pack: self.pack.clone(),
+                id: self.id,
+            }
+        }
+
+        /// Imported questions are read out as written unless told otherwise
+        pub fn fill_missing_tts(&mut self) {
+            if self.question_tts.trim().is_empty() {
+                self.question_tts = self.question.clone();
+            }
+            if self.context_information_tts.trim().is_empty() {
+                self.context_information_tts = self.context_information.clone();
+            }
+            if self.correct_tts.trim().is_empty() {
+                self.correct_tts = format!("Oikea vastaus on {}.", self.correct);
+            }
+        }
+    }
+
+    /// How a question is answered and what counts as correct
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum QuestionKind {
+        TrueFalse {
+            correct: bool,
+        },
+        /// 2-6 options in total
+        MultipleChoice {
+            correct: String,
+            incorrect: Vec<String>,
+        },
+        /// Player types a number, `scoring` decides how close is close enough
+        Numeric {
+            correct: f64,
+            tolerance: f64,
+            scoring: NumericScoring,
+        },
+        /// Player puts `items` back in this order
+        Ordering {
+            items: Vec<String>,
+        },
+        /// Player types the answer, `alternatives` are other accepted spellings. Answers
+        /// are compared ignoring case, diacritics and punctuation, allowing `max_typos`
+        /// edits, or a default depending on answer length.
+        FreeText {
+            correct: String,
+            alternatives: Vec<String>,
+            max_typos: Option<usize>,
+        },
+    }
+
+    /// Which typed numbers count as correct answers
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum NumericScoring {
+        /// Only the correct value itself
+        Exact,
+        /// Anything within `tolerance` of the correct value
+        #[default]
+        Tolerance,
+        /// Answers closest to the correct value among all answers given, even if nobody
+        /// got it right. Answers within `tolerance` are correct too.
+        Closest,
+    }
+
+    impl Default for QuestionKind {
+        fn default() -> QuestionKind {
+            QuestionKind::MultipleChoice {
+                correct: String::new(),
+                incorrect: vec![],
+            }
+        }
+    }
+
+    /// Question as written in questions.json. `kind` defaults to multiple choice, which
+    /// also accepts the original `incorrect_1`..`incorrect_3` fields. Text fields default
+    /// to empty so that validation can tell which question lacks them.
+    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
+    pub struct RawQuestion {
+        pub id: i64,
+        #[serde(default)]
+        pub category: Vec<String>,
+        #[serde(default)]
+        pub question: String,
+        #[serde(default)]
+        pub question_tts: String,
+        #[serde(default)]
+        pub context_information: String,
+        #[serde(default)]
+        pub context_information_tts: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub kind: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub correct: Option<serde_json::Value>,
+        #[serde(default)]
+        pub correct_tts: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub incorrect: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub incorrect_1: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub incorrect_2: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub incorrect_3: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub tolerance: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub numeric_scoring: Option<NumericScoring>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub items: Vec<String>,
+        #[serde(default, alias = "accepted", skip_serializing_if = "Vec::is_empty")]
+        pub alternatives: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub max_typos: Option<usize>,
+        #[serde(default, skip_serializing_if = "Media::is_empty")]
+        pub media: Media,
+    }
+
+    /// Text shown for true/false options
+    pub const TRUE_ANSWER: &str = "Totta";
+    pub const FALSE_ANSWER: &str = "Tarua";
+
+    /// How the raw question is answered. Problems are given as `field: message`.
+    pub fn question_kind(raw: &RawQuestion) -> Result<QuestionKind, String> {
+        let correct_text = match &raw.correct {
+            Some(serde_json::Value::String(correct)) => Some(correct.clone()),
+            Some(serde_json::Value::Number(correct)) => Some(correct.to_string()),
+            _ => None,
+        };
+
+        let kind = match raw.kind.as_deref().unwrap_or("multiple_choice") {
+            "true_false" => QuestionKind::TrueFalse {
+                correct: match (&raw.correct, correct_text.as_deref()) {
+                    (Some(serde_json::Value::Bool(correct)), _) => *correct,
+                    (_, Some("true")) => true,
+                    (_, Some("false")) => false,
+                    _ => return Err("correct: must be true or false".to_string()),
+                },
+            },
+            "multiple_choice" => {
+                let mut incorrect = raw.incorrect.clone();
+                if incorrect.is_empty() {
+                    incorrect = [&raw.incorrect_1, &raw.incorrect_2, &raw.incorrect_3]
+                        .into_iter()
+                        .flatten()
+                        .filter(|option| !option.is_empty())
+                        .cloned()
+                        .collect();
+                }
+                if incorrect.is_empty() || incorrect.len() > 5 {
+                    return Err(format!(
+                        "incorrect: multiple choice needs 2-6 options, got {}",
+                        incorrect.len() + 1
+                    ));
+                }
+                QuestionKind::MultipleChoice {
+                    correct: correct_text
+                        .clone()
+                        .ok_or("correct: answer is missing".to_string())?,
+                    incorrect,
+                }
+            }
+            "numeric" => QuestionKind::Numeric {
+                correct: match &raw.correct {
+                    Some(serde_json::Value::Number(correct)) => correct.as_f64(),
+                    Some(serde_json::Value::String(correct)) => correct.trim().parse().ok(),
+                    _ => None,
+                }
+                .ok_or("correct: must be a number".to_string())?,
+                tolerance: raw.tolerance.unwrap_or(0.0).abs(),
+                scoring: raw.numeric_scoring.unwrap_or_default(),
+            },
+            "ordering" => {
+                if raw.items.len() < 2 || raw.items.len() > 6 {
+                    return Err(format!(
+                        "items: ordering needs 2-6 items, got {}",
+                        raw.items.len()
+                    ));
+                }
+                QuestionKind::Ordering {
+                    items: raw.items.clone(),
+                }
+            }
+            "free_text" => QuestionKind::FreeText {
+                correct: correct_text
+                    .clone()
+                    .ok_or("correct: answer is missing".to_string())?,
+                alternatives: raw.alternatives.clone(),
+                max_typos: raw.max_typos,
+            },
+            other => return Err(format!("kind: unknown kind {}", other)),
+        };
+        Ok(kind)
+    }
+
+    impl TryFrom<RawQuestion> for Question {
+        type Error = String;
+
+        fn try_from(raw: RawQuestion) -> Result<Question, String> {
+            let kind = question_kind(&raw)
+                .map_err(|problem| format!("Question {}: {}", raw.id, problem))?;
+            Ok(Question::with_kind(raw, kind))
+        }
+    }
+
+    impl Question {
+        /// Question from the raw one with its kind already worked out by `question_kind`
+        pub fn with_kind(raw: RawQuestion, kind: QuestionKind) -> Question {
+            let correct = match &kind {
+                QuestionKind::TrueFalse { correct: true } => TRUE_ANSWER.to_string(),
+                QuestionKind::TrueFalse { correct: false } => FALSE_ANSWER.to_string(),
+                QuestionKind::MultipleChoice { correct, .. } => correct.clone(),
+                QuestionKind::Numeric { correct, .. } => correct.to_string(),
+                QuestionKind::Ordering { items } => items.join(", "),
+                QuestionKind::FreeText { correct, .. } => correct.clone(),
+            };
+
+            Question {
+                id: raw.id,
+                pack: String::new(),
+                category: raw.category,
+                question: raw.question,
+                question_tts: raw.question_tts,
+                context_information: raw.context_information,
+                context_information_tts: raw.context_information_tts,
+                correct,
+                correct_tts: raw.correct_tts,
+                kind,
+                media: raw.media,
+                answer_options: None,
+            }
+        }
+    }
+
+    impl From<Question> for RawQuestion {
+        fn from(question: Question) -> RawQuestion {
+            let mut raw = RawQuestion {
+                id: question.id,
+                category: question.category,
+                question: question.question,
+                question_tts: question.question_tts,
+                context_information: question.context_information,
+                context_information_tts: question.context_information_tts,
+                correct_tts: question.correct_tts,
+                media: question.media,
+                ..Default::default()
+            };
+            match question.kind {
+                QuestionKind::TrueFalse { correct } => {
+                    raw.kind = Some("true_false".to_string());
+                    raw.correct = Some(serde_json::Value::Bool(correct));
+                }
+                QuestionKind::MultipleChoice { correct, incorrect } => {
+                    raw.kind = Some("multiple_choice".to_string());
+                    raw.correct = Some(serde_json::Value::String(correct));
+                    raw.incorrect = incorrect;
+                }
+                QuestionKind::Numeric {
+                    correct,
+                    tolerance,
+                    scoring,
+                } => {
+                    raw.kind = Some("numeric".to_string());
+                    raw.correct = Some(serde_json::json!(correct));
+                    raw.tolerance = Some(tolerance);
+                    raw.numeric_scoring = Some(scoring);
+                }
+                QuestionKind::Ordering { items } => {
+                    raw.kind = Some("ordering".to_string());
+                    raw.items = items;
+                }
+                QuestionKind::FreeText {
+                    correct,
+                    alternatives,
+                    max_typos,
+                } => {
+                    raw.kind = Some("free_text".to_string());
+                    raw.correct = Some(serde_json::Value::String(correct));
+                    raw.alternatives = alternatives;
+                    raw.max_typos = max_typos;
+                }
+            }
+            raw
+        }
+    }
+}
+
+pub mod answers {
+    use super::structure::{NumericScoring, Question, QuestionKind, FALSE_ANSWER, TRUE_ANSWER};
+    use rand::seq::SliceRandom;
+
+    /// Items of an ordering answer are sent one per line
+    pub const ORDERING_SEPARATOR: char = '\n';
+
+    impl Question {
+        /// Options for players to pick from, None when players type the answer
+        pub fn shuffled_answer_options(&self) -> Option<Vec<String>> {
+            let mut options = match &self.kind {
+                QuestionKind::TrueFalse { .. } => {
+                    return Some(vec![TRUE_ANSWER.to_string(), FALSE_ANSWER.to_string()])
+                }
+                QuestionKind::MultipleChoice { correct, incorrect } => {
+                    let mut options = incorrect.clone();
+                    options.push(correct.clone());
+                    options
+                }
+                QuestionKind::Ordering { items } => items.clone(),
+                QuestionKind::Numeric { .. } | QuestionKind::FreeText { .. } => return None,
+            };
+            options.shuffle(&mut rand::rng());
+            Some(options)
+        }
+
+        /// How players answer, tells the player view which controls to show
+        pub fn answer_kind(&self) -> &'static str {
+            match self.kind {
+                QuestionKind::TrueFalse { .. } | QuestionKind::MultipleChoice { .. } => "choice",
+                QuestionKind::Numeric { .. } => "numeric",
+                QuestionKind::Ordering { .. } => "ordering",
+                QuestionKind::FreeText { .. } => "text",
+            }
+        }
+
+        /// Whether every answer is one of the options, so answers can be counted per option
+        pub fn has_fixed_choices(&self) -> bool {
+            self.answer_kind() == "choice"
+        }
+
+        /// Check that an answer is something this question can be answered with
+        pub fn validate_answer(&self, answer: &str) -> Result<(), String> {
+            match &self.kind {
+                QuestionKind::TrueFalse { .. } | QuestionKind::MultipleChoice { .. } => {
+                    match &self.answer_options {
+                        Some(options) if options.iter().any(|option| option == answer) => Ok(()),
+                        _ => Err("Answer is not one of the options".to_string()),
+                    }
+                }
+                QuestionKind::Numeric { .. } => match parse_number(answer) {
+                    Some(_) => Ok(()),
+                    None => Err("Answer must be a number".to_string()),
+                },
+                QuestionKind::Ordering { items } => {
+                    let mut given: Vec<&str> = answer.split(ORDERING_SEPARATOR).collect();
+                    let mut expected: Vec<&str> = items.iter().map(|item| item.as_str()).collect();
+                    given.sort();
+                    expected.sort();
+                    match given == expected {
+                        true => Ok(()),
+                        false => Err("Answer must contain every item once".to_string()),
+                    }
+                }
+                QuestionKind::FreeText { .. } => match normalize_text(answer).is_empty() {
+                    true => Err("Answer is empty".to_string()),
+                    false => Ok(()),
+                },
+            }
+        }
+
+        /// `all_answers` are every answer given to this question, needed when the
+        /// closest answers win
+        pub fn is_correct(&self, answer: &str, all_answers: &[&str]) -> bool {
+            match &self.kind {
+                QuestionKind::TrueFalse { .. } | QuestionKind::MultipleChoice { .. } => {
+                    answer == self.correct
+                }
+                QuestionKind::Numeric {
+                    correct,
+                    tolerance,
+                    scoring,
+                } => {
+                    let distance = match self.distance(answer) {
+                        Some(distance) => distance,
+                        None => return false,
+                    };
+                    match scoring {
+                        NumericScoring::Exact => distance == 0.0,
+                        NumericScoring::Tolerance => distance <= *tolerance,
+                        NumericScoring::Closest => {
+                            let closest = all_answers
+                                .iter()
+                                .filter_map(|answer| parse_number(answer))
+                                .map(|number| (number - correct).abs())
+                                .fold(f64::INFINITY, f64::min);
+                            distance <= closest.max(*tolerance)
+                        }
+                    }
+                }
+                QuestionKind::Ordering { items } => answer
+                    .split(ORDERING_SEPARATOR)
+                    .eq(items.iter().map(|item| item.as_str())),
+                QuestionKind::FreeText { .. } => {
+                    matches!(self.text_match(answer), Some((TextMatch::Accepted, _)))
+                }
+            }
+        }
+
+        /// Compare a typed answer against accepted spellings. Returns how well it
+        /// matched and the closest accepted spelling, None for other kinds.
+        pub fn text_match(&self, answer: &str) -> Option<(TextMatch, String)> {
+            let (correct, alternatives, max_typos) = match &self.kind {
+                QuestionKind::FreeText {
+                    correct,
+                    alternatives,
+                    max_typos,
+                } => (correct, alternatives, max_typos),
+                _ => return None,
+            };
+            let answer = normalize_text(answer);
+            let (typos, closest) = std::iter::once(correct)
+                .chain(alternatives.iter())
+                .map(|accepted| (levenshtein(&answer, &normalize_text(accepted)), accepted))
+                .min_by_key(|(typos, _)| *typos)?;
+
+            let allowed = match max_typos {
+                Some(max_typos) => *max_typos,
+                None => match normalize_text(closest).chars().count() {
+                    0..=3 => 0,
+                    4..=7 => 1,
+                    _ => 2,
+                },
+            };
+            // Answers a bit further off are left for the presenter to decide
+            let text_match = if typos <= allowed {
+                TextMatch::Accepted
+            } else if typos <= allowed * 2 + 1 {
+                TextMatch::Borderline
+            } else {
+                TextMatch::Rejected
+            };
+            Some((text_match, closest.clone()))
+        }
+
+        /// How far a numeric answer is from the correct value, None for other kinds
+        /// and answers which are not numbers
+        pub fn distance(&self, answer: &str) -> Option<f64> {
+            match &self.kind {
+                QuestionKind::Numeric { correct, .. } => {
+                    parse_number(answer).map(|number| (number - correct).abs())
+                }
+                _ => None,
+            }
+        }
+    }
+
+    /// Accepts both decimal point and comma
+    pub fn parse_number(answer: &str) -> Option<f64> {
+        answer
+            .trim()
+            .replace(',', ".")
+            .parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite())
+    }
+
+    /// How a typed answer compares to the accepted spellings
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum TextMatch {
+        Accepted,
+        /// Close but not close enough, presenter decides
+        Borderline,
+        Rejected,
+    }
+
+    /// Case, diacritics, punctuation and extra whitespace do not matter in typed answers
+    pub fn normalize_text(answer: &str) -> String {
+        answer
+            .to_lowercase()
+            .chars()
+            .map(|c| match c {
+                'ä' | 'å' | 'á' | 'à' | 'â' | 'ã' => 'a',
+                'ö' | 'ø' | 'ó' | 'ò' | 'ô' | 'õ' => 'o',
+                'é' | 'è' | 'ê' | 'ë' => 'e',
+                'í' | 'ì' | 'î' | 'ï' => 'i',
+                'ü' | 'ú' | 'ù' | 'û' => 'u',
+                'ç' => 'c',
+                'ñ' => 'n',
+                'š' => 's',
+                'ž' => 'z',
+                c if c.is_alphanumeric() => c,
+                _ => ' ',
+            })
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    /// Number of single character insertions, deletions and substitutions between strings
+    pub fn levenshtein(a: &str, b: &str) -> usize {
+        let b: Vec<char> = b.chars().collect();
+        let mut previous: Vec<usize> = (0..=b.len()).collect();
+        for (i, a_char) in a.chars().enumerate() {
+            let mut current = vec![i + 1];
+            for (j, b_char) in b.iter().enumerate() {
+                let substitution = previous[j] + usize::from(a_char != *b_char);
+                current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+            }
+            previous = current;
+        }
+        previous[b.len()]
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn parse_question(json: &str) -> Question {
+            serde_json::from_str(json).unwrap()
+        }
+
+        #[test]
+        fn true_false_is_answered_with_its_options() {
+            let question = parse_question(r#"{"id": 1, "kind": "true_false", "correct": false}"#);
+            assert_eq!(question.correct, FALSE_ANSWER);
+            assert_eq!(
+                question.shuffled_answer_options(),
+                Some(vec![TRUE_ANSWER.to_string(), FALSE_ANSWER.to_string()])
+            );
+            assert!(question.is_correct(FALSE_ANSWER, &[]));
+            assert!(!question.is_correct(TRUE_ANSWER, &[]));
+        }
+
+        #[test]
+        fn multiple_choice_accepts_old_incorrect_fields() {
+            let question = parse_question(
+                r#"{"id": 1, "correct": "Helsinki", "incorrect_1": "Turku", "incorrect_2": "Oulu"}"#,
+            );
+            assert_eq!(
+                question.kind,
+                QuestionKind::MultipleChoice {
+                    correct: "Helsinki".to_string(),
+                    incorrect: vec!["Turku".to_string(), "Oulu".to_string()],
+                }
+            );
+            let mut question = question;
+            question.answer_options = question.shuffled_answer_options();
+            assert!(question.validate_answer("Oulu").is_ok());
+            assert!(question.validate_answer("Tampere").is_err());
+            assert!(question.is_correct("Helsinki", &[]));
+            assert!(!question.is_correct("Oulu", &[]));
+        }
+
+        #[test]
+        fn multiple_choice_needs_two_to_six_options() {
+            let result = serde_json::from_str::<Question>(r#"{"id": 1, "correct": "Helsinki"}"#);
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn ordering_must_be_in_listed_order() {
+            let question = parse_question(
+                r#"{"id": 1, "kind": "ordering", "items": ["Yksi", "Kaksi", "Kolme"]}"#,
+            );
+            assert_eq!(question.correct, "Yksi, Kaksi, Kolme");
+            assert!(question.validate_answer("Kolme\nYksi\nKaksi").is_ok());
+            assert!(question.validate_answer("Kolme\nYksi").is_err());
+            assert!(question.is_correct("Yksi\nKaksi\nKolme", &[]));
+            assert!(!question.is_correct("Kolme\nYksi\nKaksi", &[]));
+        }
+
+        #[test]
+        fn kinds_survive_round_trip() {
+            for json in [
+                r#"{"id": 1, "kind": "true_false", "correct": true}"#,
+                r#"{"id": 2, "correct": "A", "incorrect": ["B", "C"]}"#,
+                r#"{"id": 3, "kind": "ordering", "items": ["A", "B"]}"#,
+                r#"{"id": 4, "kind": "free_text", "correct": "A", "alternatives": ["AA"], "max_typos": 1}"#,
+            ] {
+                let question = parse_question(json);
+                let saved = serde_json::to_string(&question).unwrap();
+                assert_eq!(parse_question(&saved), question);
+            }
+        }
+
+        fn numeric(scoring: &str, tolerance: f64) -> Question {
+            parse_question(&format!(
+                r#"{{"id": 1, "kind": "numeric", "correct": 1917, "tolerance": {}, "numeric_scoring": "{}"}}"#,
+                tolerance, scoring
+            ))
+        }
+
+        #[test]
+        fn numbers_accept_decimal_comma() {
+            assert_eq!(parse_number(" 3,5 "), Some(3.5));
+            assert_eq!(parse_number("3.5"), Some(3.5));
+            assert_eq!(parse_number("kolme"), None);
+            assert_eq!(parse_number("inf"), None);
+        }
+
+        #[test]
+        fn numeric_exact_needs_the_value_itself() {
+            let question = numeric("exact", 5.0);
+            assert!(question.is_correct("1917", &[]));
+            assert!(question.is_correct("1917,0", &[]));
+            assert!(!question.is_correct("1918", &[]));
+            assert!(question.validate_answer("vuonna 1917").is_err());
+        }
+
+        #[test]
+        fn numeric_tolerance_accepts_answers_within_it() {
+            let question = numeric("tolerance", 2.0);
+            assert!(question.is_correct("1915", &[]));
+            assert!(question.is_correct("1919", &[]));
+            assert!(!question.is_correct("1920", &[]));
+        }
+
+        #[test]
+        fn numeric_closest_answers_win_even_when_wrong() {
+            let question = numeric("closest", 0.0);
+            let all_answers = ["1900", "1920", "1914", "2000"];
+            assert!(question.is_correct("1920", &all_answers));
+            assert!(question.is_correct("1914", &all_answers));
+            assert!(!question.is_correct("1900", &all_answers));
+            assert!(!question.is_correct("abc", &all_answers));
+        }
+
+        #[test]
+        fn numeric_closest_also_accepts_answers_within_tolerance() {
+            let question = numeric("closest", 10.0);
+            let all_answers = ["1917", "1925"];
+            assert!(question.is_correct("1925", &all_answers));
+        }
+
+        #[test]
+        fn normalize_ignores_case_diacritics_and_punctuation() {
+            assert_eq!(normalize_text("  Jääkiekko-MM!  "), "jaakiekko mm");
+            assert_eq!(normalize_text("São   Paulo"), "sao paulo");
+            assert_eq!(normalize_text("?!"), "");
+        }
+
+        #[test]
+        fn levenshtein_counts_edits() {
+            assert_eq!(levenshtein("kissa", "kissa"), 0);
+            assert_eq!(levenshtein("kisa", "kissa"), 1);
+            assert_eq!(levenshtein("kassa", "kissa"), 1);
+            assert_eq!(levenshtein("kissat", "kissa"), 1);
+            assert_eq!(levenshtein("", "abc"), 3);
+            assert_eq!(levenshtein("koira", "kissa"), 3);
+        }
+
+        fn free_text(max_typos: Option<usize>) -> Question {
+            Question {
+                kind: QuestionKind::FreeText {
+                    correct: "Sibelius".to_string(),
+                    alternatives: vec!["Jean Sibelius".to_string()],
+                    max_typos,
+                },
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn free_text_allows_typos_by_length() {
+            let question = free_text(None);
+            assert!(question.is_correct("sibelius", &[]));
+            assert!(question.is_correct("Jean Sibelijus", &[]));
+            assert!(question.is_correct("Sibelus", &[]));
+            assert!(question.is_correct("Sipeljus", &[]));
+            assert!(!question.is_correct("Sipeljuss", &[]));
+        }
+
+        #[test]
+        fn free_text_answers_a_bit_further_off_go_to_review() {
+            let question = free_text(Some(0));
+            assert_eq!(
+                question.text_match("jean sibelius"),
+                Some((TextMatch::Accepted, "Jean Sibelius".to_string()))
+            );
+            assert_eq!(
+                question.text_match("Sibelus"),
+                Some((TextMatch::Borderline, "Sibelius".to_string()))
+            );
+            assert_eq!(
+                question.text_match("Mozart"),
+                Some((TextMatch::Rejected, "Sibelius".to_string()))
+            );
+            assert_eq!(Question::default().text_match("Sibelius"), None);
+        }
+    }
+}
+
+pub mod media {
+    use super::structure::{Media, Root};
+    use std::path::{Component, Path};
+
+    /// Media files are read from here and served under MEDIA_URL_PREFIX
+    pub const MEDIA_DIR: &str = "web/media";
+    pub const MEDIA_URL_PREFIX: &str = "/media/";
+
+    const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "svg"];
+    const AUDIO_EXTENSIONS: &[&str] = &["mp3", "ogg", "wav", "m4a"];
+    const VIDEO_EXTENSIONS: &[&str] = &["mp4", "webm", "ogv"];
+
+    impl Media {
+        pub fn is_empty(&self) -> bool {
+            self.image.is_none() && self.audio.is_none() && self.video.is_none()
+        }
+
+        /// Same media with file names turned into URLs the presenter can load
+        pub fn urls(&self) -> Media {
+            Media {
+                image: self.image.as_deref().map(media_url),
+                audio: self.audio.as_deref().map(media_url),
+                video: self.video.as_deref().map(media_url),
+            }
+        }
+    }
+
+    fn is_remote(path: &str) -> bool {
+        path.starts_with("http://") || path.starts_with("https://")
+    }
+
+    fn media_url(path: &str) -> String {
+        match is_remote(path) {
+            true => path.to_string(),
+            false => format!("{}{}", MEDIA_URL_PREFIX, path),
+        }
+    }
+
+    /// Check that a media file has a suitable extension, stays inside media directory
+    /// and exists
+    fn check_media_file(media_dir: &Path, path: &str, extensions: &[&str]) -> Result<(), String> {
+        let relative = Path::new(path);
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return Err(format!(
+                "{} must be a plain path inside media directory",
+                path
+            ));
+        }
+        let extension = relative
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase())
+            .unwrap_or_default();
+        if !extensions.contains(&extension.as_str()) {
+            return Err(format!(
+                "{} has unsupported file type, expected one of: {}",
+                path,
+                extensions.join(", ")
+            ));
+        }
+        if !media_dir.join(relative).is_file() {
+            return Err(format!("{} was not found in {}", path, media_dir.display()));
+        }
+        Ok(())
+    }
+
+    /// Every problem with referenced media files, one line per problem
+    pub fn validate_media(all_questions: &Root, media_dir: &str) -> Vec<String> {
+        let media_dir = Path::new(media_dir);
+        let mut problems: Vec<String> = vec![];
+
+        for question in all_questions.questions.iter() {
+            let media = &question.media;
+            let checks = [
+                ("image", &media.image, IMAGE_EXTENSIONS),
+                ("audio", &media.audio, AUDIO_EXTENSIONS),
+                ("video", &media.video, VIDEO_EXTENSIONS),
+            ];
+            for (field, path, extensions) in checks {
+                let path = match path {
+                    Some(path) => path,
+                    None => continue,
+                };
+                if field == "video" && is_remote(path) {
+                    continue;
+                }
+                if let Err(error) = check_media_file(media_dir, path, extensions) {
+                    problems.push(format!(
+                        "Question {}: media.{}: {}",
+                        question.qualified_id(),
+                        field,
+                        error
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+pub mod validation {
+    use super::answers::normalize_text;
+    use super::structure::{question_kind, Question, QuestionId, QuestionKind, RawQuestion, Root};
+    use std::collections::HashMap;
+
+    fn is_blank(text: &str) -> bool {
+        text.trim().is_empty()
+    }
+
+    /// Questions of a pack as written in the file. Broken questions are left out and
+    /// every one of them is reported, not just the first.
+    pub fn parse_questions(
+        values: Vec<serde_json::Value>,
+        pack_name: &str,
+    ) -> (Vec<Question>, Vec<String>) {
+        let mut questions: Vec<Question> = vec![];
+        let mut problems: Vec<String> = vec![];
+        for (position, value) in values.into_iter().enumerate() {
+            let name = match value.get("id").and_then(|id| id.as_i64()) {
+                Some(id) => QuestionId {
+                    pack: pack_name.to_string(),
+                    id,
+                }
+                .to_string(),
+                None => format!("number {} in the file", position + 1),
+            };
+            let raw = match serde_json::from_value::<RawQuestion>(value) {
+                Ok(raw) => raw,
+                Err(error) => {
+                    problems.push(format!("Question {}: {}", name, error));
+                    continue;
+                }
+            };
+            match question_kind(&raw) {
+                Ok(kind) => {
+                    let mut question = Question::with_kind(raw, kind);
+                    question.pack = pack_name.to_string();
+                    questions.push(question);
+                }
+                Err(problem) => problems.push(format!("Question {}: {}", name, problem)),
+            }
+        }
+        (questions, problems)
+    }
+
+    /// Every problem in a question pack, one line per problem naming question ID and field
+    pub fn validate_questions(all_questions: &Root) -> Vec<String> {
+        let mut problems: Vec<String> = vec![];
+
+        let mut positions: HashMap<QuestionId, Vec<String>> = HashMap::new();
+        for (position, question) in all_questions.questions.iter().enumerate() {
+            positions
+                .entry(question.qualified_id())
+                .or_default()
+                .push((position + 1).to_string());
+        }
+        let mut duplicates: Vec<(&QuestionId, &Vec<String>)> = positions
+            .iter()
+            .filter(|(_, found_at)| found_at.len() > 1)
+            .collect();
+        duplicates.sort();
+        for (id, found_at) in duplicates {
+            problems.push(format!(
+                "Question {}: id: shared by questions number {} in the file",
+                id,
+                found_at.join(", ")
+            ));
+        }
+
+        for question in all_questions.questions.iter() {
+            validate_question(question, &all_questions.metadata.categories, &mut problems);
+        }
+        problems
+    }
+
+    pub fn validate_question(
+        question: &Question,
+        known_categories: &[String],
+        problems: &mut Vec<String>,
+    ) {
+        let mut problem = |field: &str, message: String| {
+            problems.push(format!(
+                "Question {}: {}: {}",
+                question.qualified_id(),
+                field,
+                message
+            ));
+        };
+
+        if is_blank(&question.question) {
+            problem("question", "is empty".to_string());
+        }
+        // Context is optional, but if there is some it must be read out too
+        let has_context = !is_blank(&question.context_information);
+        let tts_texts = [
+            ("question_tts", &question.question_tts, true),
+            (
+                "context_information_tts",
+                &question.context_information_tts,
+                has_context,
+            ),
+            ("correct_tts", &question.correct_tts, true),
+        ];
+        for (field, text, required) in tts_texts {
+            if required && is_blank(text) {
+                problem(field, "is missing or empty".to_string());
+            }
+        }
+
+        if question.category.is_empty() {
+            problem("category", "is empty".to_string());
+        }
+        for category in question.category.iter() {
+            if is_blank(category) {
+                problem("category", "contains an empty category".to_string());
+            } else if !known_categories.is_empty() && !known_categories.contains(category) {
+                problem("category", format!("unknown category {}", category));
+            }
+        }
+
+        match &question.kind {
+            QuestionKind::TrueFalse { .. } | QuestionKind::Numeric { .. } => (),
+            QuestionKind::MultipleChoice { correct, incorrect } => {
+                if is_blank(correct) {
+                    problem("correct", "is empty".to_string());
+                }
+                let mut seen: Vec<String> = vec![normalize_text(correct)];
+                for option in incorrect.iter() {
+                    if is_blank(option) {
+                        problem("incorrect", "contains an empty option".to_string());
+                        continue;
+                    }
+                    let normalized = normalize_text(option);
+                    if normalized == seen[0] {
+                        problem(
+                            "incorrect",
+                            format!("{} is the same as the correct answer", option),
+                        );
+                    } else if seen.contains(&normalized) {
+                        problem("incorrect", format!("{} is listed twice", option));
+                    }
+                    seen.push(normalized);
+                }
+            }
+            QuestionKind::Ordering { items } => {
+                let mut seen: Vec<String> = vec![];
+                for item in items.iter() {
+                    let normalized = normalize_text(item);
+                    if is_blank(item) {
+                        problem("items", "contains an empty item".to_string());
+                    } else if seen.contains(&normalized) {
+                        problem("items", format!("{} is listed twice", item));
+                    }
+                    seen.push(normalized);
+                }
+            }
+            QuestionKind::FreeText {
+                correct,
+                alternatives,
+                ..
+            } => {
+                if normalize_text(correct).is_empty() {
+                    problem("correct", "is empty".to_string());
+                }
+                if alternatives
+                    .iter()
+                    .any(|alternative| normalize_text(alternative).is_empty())
+                {
+                    problem("alternatives", "contains an empty answer".to_string());
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::questions::structure::Metadata;
+
+        fn valid_question(id: i64) -> Question {
+            Question {
+                id,
+                pack: "testi".to_string(),
+                category: vec!["maantieto".to_string()],
+                question: "Mikä on Suomen pääkaupunki?".to_string(),
+                question_tts: "Mikä on Suomen pääkaupunki?".to_string(),
+                correct: "Helsinki".to_string(),
+                correct_tts: "Oikea vastaus on Helsinki.".to_string(),
+                kind: QuestionKind::MultipleChoice {
+                    correct: "Helsinki".to_string(),
+                    incorrect: vec!["Turku".to_string(), "Tampere".to_string()],
+                },
+                ..Default::default()
+            }
+        }
+
+        fn pack(questions: Vec<Question>, categories: &[&str]) -> Root {
+            Root {
+                metadata: Metadata {
+                    categories: categories
+                        .iter()
+                        .map(|category| category.to_string())
+                        .collect(),
+                    ..Default::default()
+                },
+                questions,
+            }
+        }
+
+        #[test]
+        fn valid_pack_has_no_problems() {
+            let root = pack(vec![valid_question(1), valid_question(2)], &["maantieto"]);
+            assert_eq!(validate_questions(&root), Vec::<String>::new());
+        }
+
+        #[test]
+        fn every_broken_question_is_reported() {
+            let values: Vec<serde_json::Value> = serde_json::from_str(
+                r#"[
+                    {"id": 1, "correct": "Helsinki", "incorrect": ["Turku"]},
+                    {"id": 2, "kind": "true_false", "correct": "ehkä"},
+                    {"id": 3, "kind": "numeric", "correct": "paljon"},
+                    {"id": 4, "kind": "arvaus", "correct": "x"},
+                    {"id": 5, "kind": "ordering", "items": ["a"]},
+                    {"id": 6, "category": "maantieto"},
+                    {"correct": "Helsinki"}
+                ]"#,
+            )
+            .unwrap();
+            let (questions, problems) = parse_questions(values, "testi");
+            assert_eq!(questions.len(), 1);
+            assert_eq!(questions[0].pack, "testi");
+            assert_eq!(
+                problems,
+                vec![
+                    "Question testi/2: correct: must be true or false",
+                    "Question testi/3: correct: must be a number",
+                    "Question testi/4: kind: unknown kind arvaus",
+                    "Question testi/5: items: ordering needs 2-6 items, got 1",
+                    "Question testi/6: invalid type: string \"maantieto\", expected a sequence",
+                    "Question number 7 in the file: missing field `id`",
+                ]
+            );
+        }
+
+        #[test]
+        fn duplicate_ids_are_reported_with_positions() {
+            let root = pack(
+                vec![valid_question(1), valid_question(2), valid_question(1)],
+                &[],
+            );
+            assert_eq!(
+                validate_questions(&root),
+                vec!["Question testi/1: id: shared by questions number 1, 3 in the file"]
+            );
+        }
+
+        #[test]
+        fn missing_texts_are_reported_by_field() {
+            let mut question = valid_question(1);
+            question.question = " ".to_string();
+            question.question_tts = String::new();
+            question.context_information = "Kaupunki perustettiin 1550.".to_string();
+            question.category = vec![];
+            assert_eq!(
+                validate_questions(&pack(vec![question], &[])),
+                vec![
+                    "Question testi/1: question: is empty",
+                    "Question testi/1: question_tts: is missing or empty",
+                    "Question testi/1: context_information_tts: is missing or empty",
+                    "Question testi/1: category: is empty",
+                ]
+            );
+        }
+
+        #[test]
+        fn unknown_categories_are_reported() {
+            let root = pack(vec![valid_question(1)], &["historia"]);
+            assert_eq!(
+                validate_questions(&root),
+                vec!["Question testi/1: category: unknown category maantieto"]
+            );
+        }
+
+        #[test]
+        fn repeated_options_are_reported() {
+            let mut question = valid_question(1);
+            question.kind = QuestionKind::MultipleChoice {
+                correct: "Helsinki".to_string(),
+                incorrect: vec![
+                    "helsinki".to_string(),
+                    "Turku".to_string(),
+                    "Turku!".to_string(),
+                ],
+            };
+            assert_eq!(
+                validate_questions(&pack(vec![question], &[])),
+                vec![
+                    "Question testi/1: incorrect: helsinki is the same as the correct answer",
+                    "Question testi/1: incorrect: Turku! is listed twice",
+                ]
+            );
+        }
+    }
+}
+
+pub mod spreadsheet {
+    use super::structure::{Metadata, NumericScoring, Question, QuestionKind, RawQuestion, Root};
+    use super::validation::validate_question;
+    use crate::helpers::time_helpers::unix_time_s;
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::Path;
+
+    /// Columns written on export. On import only `question` and `correct` are required
+    /// and columns may be in any order.
+    pub const COLUMNS: &[&str] = &[
+        "id",
+        "category",
+        "kind",
+        "question",
+        "question_tts",
+        "context_information",
+        "context_information_tts",
+        "correct",
+        "correct_tts",
+        "incorrect_1",
+        "incorrect_2",
+        "incorrect_3",
+        "incorrect_4",
+        "incorrect_5",
+        "tolerance",
+        "numeric_scoring",
+        "items",
+        "alternatives",
+        "max_typos",
+        "image",
+        "audio",
+        "video",
+    ];
+
+    /// Separates categories, ordering items and free text alternatives inside one cell.
+    /// The separator itself is written as `\|` and a backslash as `\\`.
+    const LIST_SEPARATOR: char = '|';
+    const LIST_ESCAPE: char = '\\';
+
+    /// Tab separated for .tsv files, comma separated otherwise
+    pub fn delimiter_for(path: &Path) -> char {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("tsv") | Some("tab") => '\t',
+            _ => ',',
+        }
+    }
+
+    /// Split CSV data into rows of cells. Quoted cells may contain delimiters, line
+    /// breaks and doubled quotes.
+    pub fn parse_delimited(data: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+        let mut rows: Vec<Vec<String>> = vec![];
+        let mut row: Vec<String> = vec![];
+        let mut cell = String::new();
+        let mut quoted = false;
+        let mut line = 1;
+        let mut quote_started_on = 0;
+        let mut chars = data.trim_start_matches('\u{feff}').chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') => match chars.peek() {
+                    Some('"') => {
+                        cell.push('"');
+                        chars.next();
+                    }
+                    _ => quoted = false,
+                },
+                (true, c) => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    cell.push(c);
+                }
+                (false, '"') if cell.is_empty() => {
+                    quoted = true;
+                    quote_started_on = line;
+                }
+                (false, '\r') => (),
+                (false, '\n') => {
+                    row.push(std::mem::take(&mut cell));
+                    rows.push(std::mem::take(&mut row));
+                    line += 1;
+                }
+                (false, c) if c == delimiter => row.push(std::mem::take(&mut cell)),
+                (false, c) => cell.push(c),
+            }
+        }
+        if quoted {
+            return Err(format!(
+                "Line {}: quoted cell is never closed",
+                quote_started_on
+            ));
+        }
+        if !cell.is_empty() || !row.is_empty() {
+            row.push(cell);
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    fn format_cell(cell: &str, delimiter: char) -> String {
+        if cell.contains(delimiter)
+            || cell.contains('"')
+            || cell.contains('\n')
+            || cell.contains('\r')
+            || cell.trim() != cell
+        {
+            format!("\"{}\"", cell.replace('"', "\"\""))
+        } else {
+            cell.to_string()
+        }
+    }
+
+    pub fn format_delimited(rows: &[Vec<String>], delimiter: char) -> String {
+        let mut data = String::new();
+        for row in rows.iter() {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|cell| format_cell(cell, delimiter))
+                .collect();
+            data.push_str(&cells.join(&delimiter.to_string()));
+            data.push_str("\r\n");
+        }
+        data
+    }
+
+    fn split_list(cell: &str) -> Vec<String> {
+        let mut items: Vec<String> = vec![];
+        let mut item = String::new();
+        let mut chars = cell.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                LIST_ESCAPE => match chars.peek() {
+                    Some(&next) if next == LIST_SEPARATOR || next == LIST_ESCAPE => {
+                        item.push(next);
+                        chars.next();
+                    }
+                    _ => item.push(c),
+                },
+                LIST_SEPARATOR => items.push(std::mem::take(&mut item)),
+                c => item.push(c),
+            }
+        }
+        items.push(item);
+        items
+            .iter()
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+
+    fn join_list(items: &[String]) -> String {
+        items
+            .iter()
+            .map(|item| {
+                item.replace(LIST_ESCAPE, &format!("{0}{0}", LIST_ESCAPE))
+                    .replace(
+                        LIST_SEPARATOR,
+                        &format!("{}{}", LIST_ESCAPE, LIST_SEPARATOR),
+                    )
+            })
+            .collect::<Vec<String>>()
+            .join(&format!(" {} ", LIST_SEPARATOR))
+    }
+
+    fn parse_cell<T: std::str::FromStr>(cell: &str, column: &str) -> Result<Option<T>, String> {
+        match cell.trim() {
+            "" => Ok(None),
+            value => match value.parse::<T>() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(format!("{}: {} is not a valid number", column, value)),
+            },
+        }
+    }
+
+    /// Question from one spreadsheet row. Empty TTS cells are read out as written.
+    fn question_from_row(
+        cell: &dyn Fn(&str) -> String,
+        next_id: &mut i64,
+    ) -> Result<Question, String> {
+        let id = match parse_cell::<i64>(&cell("id"), "id")? {
+            Some(id) => id,
+            None => {
+                *next_id += 1;
+                *next_id - 1
+            }
+        };
+        let correct = cell("correct").trim().to_string();
+        let numeric_scoring = match cell("numeric_scoring").trim() {
+            "" => None,
+            scoring => Some(
+                serde_json::from_value::<NumericScoring>(serde_json::json!(scoring))
+                    .map_err(|_| format!("numeric_scoring: unknown scoring {}", scoring))?,
+            ),
+        };
+
+        let raw = RawQuestion {
+            id,
+            category: split_list(&cell("category")),
+            question: cell("question").trim().to_string(),
+            question_tts: cell("question_tts").trim().to_string(),
+            context_information: cell("context_information").trim().to_string(),
+            context_information_tts: cell("context_information_tts").trim().to_string(),
+            kind: match cell("kind").trim() {
+                "" => None,
+                kind => Some(kind.to_string()),
+            },
+            correct_tts: cell("correct_tts").trim().to_string(),
+            correct: match correct.is_empty() {
+                true => None,
+                false => Some(serde_json::Value::String(correct)),
+            },
+            incorrect: (1..=5)
+                .map(|number| cell(&format!("incorrect_{}", number)).trim().to_string())
+                .filter(|option| !option.is_empty())
+                .collect(),
+            tolerance: parse_cell::<f64>(&cell("tolerance"), "tolerance")?,
+            numeric_scoring,
+            items: split_list(&cell("items")),
+            alternatives: split_list(&cell("alternatives")),
+            max_typos: parse_cell::<usize>(&cell("max_typos"), "max_typos")?,
+            media: super::structure::Media {
+                image: Some(cell("image").trim().to_string()).filter(|path| !path.is_empty()),
+                audio: Some(cell("audio").trim().to_string()).filter(|path| !path.is_empty()),
+                video: Some(cell("video").trim().to_string()).filter(|path| !path.is_empty()),
+            },
+            ..Default::default()
+        };
+        let mut question = Question::try_from(raw)?;
+        question.fill_missing_tts();
+        Ok(question)
+    }
+
+    /// Read questions from spreadsheet data. Rows without an ID are numbered after the
+    /// largest ID in the spreadsheet, starting from `first_free_id` at least. Every problem
+    /// is reported with its row number.
+    pub fn import_questions(
+        data: &str,
+        delimiter: char,
+        pack_name: &str,
+        metadata: &Metadata,
+        first_free_id: i64,
+    ) -> Result<Vec<Question>, Vec<String>> {
+        let rows = parse_delimited(data, delimiter).map_err(|error| vec![error])?;
+        let (header, rows) = match rows.split_first() {
+            Some((header, rows)) => (header, rows),
+            None => return Err(vec!["Spreadsheet is empty".to_string()]),
+        };
+
+        let header: Vec<String> = header
+            .iter()
+            .map(|column| column.trim().to_lowercase())
+            .collect();
+        let mut problems: Vec<String> = vec![];
+        for column in header.iter() {
+            if !column.is_empty() && !COLUMNS.contains(&column.as_str()) {
+                problems.push(format!("Header: unknown column {}", column));
+            }
+        }
+        for required in ["question", "correct"] {
+            if !header.iter().any(|column| column == required) {
+                problems.push(format!("Header: column {} is missing", required));
+            }
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        let mut next_id = rows
+            .iter()
+            .filter_map(|row| {
+                let index = header.iter().position(|column| column == "id")?;
+                row.get(index)?.trim().parse::<i64>().ok()
+            })
+            .max()
+            .map(|id| id + 1)
+            .unwrap_or(0)
+            .max(first_free_id);
+
+        let mut questions: Vec<Question> = vec![];
+        let mut rows_by_id: HashMap<i64, Vec<String>> = HashMap::new();
+        for (index, row) in rows.iter().enumerate() {
+            // Header is row 1
+            let row_number = index + 2;
+            if row.iter().all(|cell| cell.trim().is_empty()) {
+                continue;
+            }
+            let cell = |column: &str| -> String {
+                header
+                    .iter()
+                    .position(|name| name == column)
+                    .and_then(|position| row.get(position))
+                    .cloned()
+                    .unwrap_or_default()
+            };
+            let mut question = match question_from_row(&cell, &mut next_id) {
+                Ok(question) => question,
+                Err(error) => {
+                    problems.push(format!("Row {}: {}", row_number, error));
+                    continue;
+                }
+            };
+            question.pack = pack_name.to_string();
+
+            let mut row_problems: Vec<String> = vec![];
+            validate_question(&question, &metadata.categories, &mut row_problems);
+            problems.extend(
+                row_problems
+                    .iter()
+                    .map(|problem| format!("Row {}: {}", row_number, problem)),
+            );
+            rows_by_id
+                .entry(question.id)
+                .or_default()
+                .push(row_number.to_string());
+            questions.push(question);
+        }
+
+        let mut duplicates: Vec<(&i64, &Vec<String>)> = rows_by_id
+            .iter()
+            .filter(|(_, found_on)| found_on.len() > 1)
+            .collect();
+        duplicates.sort();
+        for (id, found_on) in duplicates {
+            problems.push(format!(
+                "Rows {}: id {} is used more than once",
+                found_on.join(", "),
+                id
+            ));
+        }
+
+        match problems.is_empty() {
+            true => Ok(questions),
+            false => Err(problems),
+        }
+    }
+
+    fn question_to_row(question: &Question) -> Vec<String> {
+        let mut row: HashMap<String, String> = HashMap::new();
+        row.insert("id".to_string(), question.id.to_string());
+        row.insert("category".to_string(), join_list(&question.category));
+        row.insert("question".to_string(), question.question.clone());
+        row.insert("question_tts".to_string(), question.question_tts.clone());
+        row.insert(
+            "context_information".to_string(),
+            question.context_information.clone(),
+        );
+        row.insert(
+            "context_information_tts".to_string(),
+            question.context_information_tts.clone(),
+        );
+        row.insert("correct_tts".to_string(), question.correct_tts.clone());
+        match &question.kind {
+            QuestionKind::TrueFalse { correct } => {
+                row.insert("kind".to_string(), "true_false".to_string());
+                row.insert("correct".to_string(), correct.to_string());
+            }
+            QuestionKind::MultipleChoice { correct, incorrect } => {
+                row.insert("kind".to_string(), "multiple_choice".to_string());
+                row.insert("correct".to_string(), correct.clone());
+                for (index, option) in incorrect.iter().enumerate() {
+                    row.insert(format!("incorrect_{}", index + 1), option.clone());
+                }
+            }
+            QuestionKind::Numeric {
+                correct,
+                tolerance,
+                scoring,
+            } => {
+                row.insert("kind".to_string(), "numeric".to_string());
+                row.insert("correct".to_string(), correct.to_string());
+                if *tolerance != 0.0 {
+                    row.insert("tolerance".to_string(), tolerance.to_string());
+                }
+                if let Ok(serde_json::Value::String(scoring)) = serde_json::to_value(scoring) {
+                    row.insert("numeric_scoring".to_string(), scoring);
+                }
+            }
+            QuestionKind::Ordering { items } => {
+                row.insert("kind".to_string(), "ordering".to_string());
+                row.insert("items".to_string(), join_list(items));
+            }
+            QuestionKind::FreeText {
+                correct,
+                alternatives,
+                max_typos,
+            } => {
+                row.insert("kind".to_string(), "free_text".to_string());
+                row.insert("correct".to_string(), correct.clone());
+                row.insert("alternatives".to_string(), join_list(alternatives));
+                if let Some(max_typos) = max_typos {
+                    row.insert("max_typos".to_string(), max_typos.to_string());
+                }
+            }
+        }
+        let media = [
+            ("image", &question.media.image),
+            ("audio", &question.media.audio),
+            ("video", &question.media.video),
+        ];
+        for (column, path) in media {
+            if let Some(path) = path {
+                row.insert(column.to_string(), path.clone());
+            }
+        }
+
+        COLUMNS
+            .iter()
+            .map(|column| row.remove(*column).unwrap_or_default())
+            .collect()
+    }
+
+    /// Spreadsheet with a header row and one row per question
+    pub fn export_questions(pack: &Root, delimiter: char) -> String {
+        let mut rows: Vec<Vec<String>> =
+            vec![COLUMNS.iter().map(|column| column.to_string()).collect()];
+        rows.extend(pack.questions.iter().map(question_to_row));
+        format_delimited(&rows, delimiter)
+    }
+
+    /// Write pack with the same indentation as hand written packs
+    pub fn save_pack(pack: &Root, path: &Path) -> Result<(), String> {
+        let mut data: Vec<u8> = vec![];
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+        let mut serializer = serde_json::Serializer::with_formatter(&mut data, formatter);
+        if let Err(error) = serde::Serialize::serialize(pack, &mut serializer) {
+            return Err(format!("{}", error));
+        }
+        let mut file = match File::create(path) {
+            Ok(file) => file,
+            Err(error) => return Err(format!("{}", error)),
+        };
+        match file.write_all(&data) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(format!("{}", error)),
+        }
+    }
+
+    /// Existing pack for importing into, or a new empty one named after the file
+    pub fn open_pack(pack_path: &Path) -> Result<(Root, String), Vec<String>> {
+        let pack_name = pack_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let pack = match std::fs::read_to_string(pack_path) {
+            Ok(existing) => match serde_json::from_str::<Root>(&existing) {
+                Ok(pack) => pack,
+                Err(error) => {
+                    return Err(vec![format!("{}: {}", pack_path.display(), error)]);
+                }
+            },
+            Err(_) => Root {
+                metadata: Metadata {
+                    time: unix_time_s() as i64,
+                    title: pack_name.clone(),
+                    ..Default::default()
+                },
+                questions: vec![],
+            },
+        };
+        Ok((pack, pack_name))
+    }
+
+    /// ID after the largest one in the pack
+    pub fn first_free_id(questions: &[Question]) -> i64 {
+        questions
+            .iter()
+            .map(|question| question.id + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Questions with the same ID as an imported one are replaced by it, the rest of the
+    /// imported questions are added after the existing ones. Returns how many questions
+    /// were updated and how many added.
+    pub fn merge_questions(
+        questions: &mut Vec<Question>,
+        imported: Vec<Question>,
+    ) -> (usize, usize) {
+        let mut updated = 0;
+        let mut added = 0;
+        for question in imported {
+            match questions
+                .iter_mut()
+                .find(|existing| existing.id == question.id)
+            {
+                Some(existing) => {
+                    *existing = question;
+                    updated += 1;
+                }
+                None => {
+                    questions.push(question);
+                    added += 1;
+                }
+            }
+        }
+        (updated, added)
+    }
+
+    /// Update questions of a pack from a spreadsheet by ID, or with `replace` have the
+    /// spreadsheet's rows replace all of the pack's questions. Metadata of an existing
+    /// pack is kept.
+    pub fn import_file(
+        source: &Path,
+        pack_path: &Path,
+        replace: bool,
+    ) -> Result<String, Vec<String>> {
+        let data = match std::fs::read_to_string(source) {
+            Ok(data) => data,
+            Err(error) => return Err(vec![format!("{}: {}", source.display(), error)]),
+        };
+        let (mut pack, pack_name) = open_pack(pack_path)?;
+        if replace {
+            pack.questions.clear();
+        }
+
+        let imported = import_questions(
+            &data,
+            delimiter_for(source),
+            &pack_name,
+            &pack.metadata,
+            first_free_id(&pack.questions),
+        )?;
+        let count = imported.len();
+        let (updated, added) = merge_questions(&mut pack.questions, imported);
+        save_pack(&pack, pack_path).map_err(|error| vec![error])?;
+        Ok(format!(
+            "Imported {} questions from {} to {}: {} updated, {} added",
+            count,
+            source.display(),
+            pack_path.display(),
+            updated,
+            added
+        ))
+    }
+
+    pub fn export_file(pack_path: &Path, target: &Path) -> Result<String, Vec<String>> {
+        let data = match std::fs::read_to_string(pack_path) {
+            Ok(data) => data,
+            Err(error) => return Err(vec![format!("{}: {}", pack_path.display(), error)]),
+        };
+        let pack = match serde_json::from_str::<Root>(&data) {
+            Ok(pack) => pack,
+            Err(error) => return Err(vec![format!("{}: {}", pack_path.display(), error)]),
+        };
+        let mut file = match File::create(target) {
+            Ok(file) => file,
+            Err(error) => return Err(vec![format!("{}: {}", target.display(), error)]),
+        };
+        match file.write_all(export_questions(&pack, delimiter_for(target)).as_bytes()) {
+            Ok(_) => Ok(format!(
+                "Exported {} questions from {} to {}",
+                pack.questions.len(),
+                pack_path.display(),
+                target.display()
+            )),
+            Err(error) => Err(vec![format!("{}: {}", target.display(), error)]),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn test_pack() -> Root {
+            let questions = r#"[
+                {"id": 0, "category": ["maantieto", "a | b"], "question": "Pääkaupunki?",
+                 "question_tts": "Mikä on pääkaupunki?", "correct": "Helsinki",
+                 "correct_tts": "Oikea vastaus on Helsinki.", "incorrect": ["Turku", "Oulu, \"vanha\""]},
+                {"id": 1, "category": ["historia"], "question": "Vuosi?", "question_tts": "Vuosi?",
+                 "context_information": "Rivi\nja toinen", "context_information_tts": "Rivi",
+                 "kind": "numeric", "correct": 1917.5, "tolerance": 2, "numeric_scoring": "closest",
+                 "correct_tts": "Vuonna 1917."},
+                {"id": 2, "category": ["luonto"], "question": "Järjestä", "question_tts": "Järjestä",
+                 "kind": "ordering", "items": ["Yksi", "Ka|ksi", "Kol\\me"], "correct_tts": "Näin."},
+                {"id": 3, "category": ["luonto"], "question": "Lintu?", "question_tts": "Lintu?",
+                 "kind": "free_text", "correct": "Laulujoutsen", "alternatives": ["joutsen"],
+                 "max_typos": 1, "correct_tts": "Laulujoutsen.", "media": {"image": "joutsen.jpg"}},
+                {"id": 4, "category": ["luonto"], "question": "Totta?", "question_tts": "Totta?",
+                 "kind": "true_false", "correct": false, "correct_tts": "Tarua."}
+            ]"#;
+            let mut questions: Vec<Question> = serde_json::from_str(questions).unwrap();
+            for question in questions.iter_mut() {
+                question.pack = "testi".to_string();
+            }
+            Root {
+                metadata: Metadata::default(),
+                questions,
+            }
+        }
+
+        #[test]
+        fn export_and_import_round_trip() {
+            let pack = test_pack();
+            for delimiter in [',', '\t'] {
+                let data = export_questions(&pack, delimiter);
+                let imported =
+                    import_questions(&data, delimiter, "testi", &pack.metadata, 0).unwrap();
+                assert_eq!(imported, pack.questions);
+            }
+        }
+
+        #[test]
+        fn list_separator_inside_item_is_escaped() {
+            let items = vec!["a | b".to_string(), "c\\d".to_string(), "e".to_string()];
+            assert_eq!(join_list(&items), "a \\| b | c\\\\d | e");
+            assert_eq!(split_list(&join_list(&items)), items);
+        }
+
+        #[test]
+        fn rows_without_id_are_numbered_after_existing_questions() {
+            let data = "id,category,question,correct,incorrect_1\n\
+                        ,maantieto,Uusi?,A,B\n\
+                        7,maantieto,Muutettu?,C,D\n\
+                        ,maantieto,Toinen uusi?,E,F\n";
+            let imported = import_questions(data, ',', "testi", &Metadata::default(), 10).unwrap();
+            let ids: Vec<i64> = imported.iter().map(|question| question.id).collect();
+            assert_eq!(ids, vec![10, 7, 11]);
+            assert_eq!(imported[0].correct_tts, "Oikea vastaus on A.");
+        }
+
+        #[test]
+        fn import_merges_by_id() {
+            let mut questions = test_pack().questions;
+            let mut changed = questions[1].clone();
+            changed.question = "Muutettu?".to_string();
+            let mut new = questions[0].clone();
+            new.id = 5;
+
+            assert_eq!(merge_questions(&mut questions, vec![changed, new]), (1, 1));
+            assert_eq!(questions.len(), 6);
+            assert_eq!(questions[1].question, "Muutettu?");
+            assert_eq!(questions[5].id, 5);
+        }
+
+        #[test]
+        fn problems_are_reported_by_row() {
+            let data = "id,question,correct,incorrect_1,tolerance,oops\n\
+                        1,Kysymys?,A,B,x,\n\
+                        1,Kysymys?,A,,,\n";
+            let problems = import_questions(data, ',', "testi", &Metadata::default(), 0);
+            assert_eq!(
+                problems,
+                Err(vec!["Header: unknown column oops".to_string()])
+            );
+
+            let data = data.replace(",oops", ",").replace(",x,", ",1,");
+            let problems = import_questions(&data, ',', "testi", &Metadata::default(), 0);
+            assert_eq!(
+                problems,
+                Err(vec![
+                    "Row 2: Question testi/1: category: is empty".to_string(),
+                    "Row 3: Question 1: incorrect: multiple choice needs 2-6 options, got 1"
+                        .to_string(),
+                ])
+            );
+        }
+
+        #[test]
+        fn unclosed_quote_is_reported_with_line() {
+            assert_eq!(
+                parse_delimited("a,b\n\"c,d\n", ','),
+                Err("Line 2: quoted cell is never closed".to_string())
+            );
+        }
+    }
+}
+
+pub mod importer {
+    use super::spreadsheet::{self, first_free_id, open_pack, save_pack};
+    use super::structure::{Question, RawQuestion};
+    use super::validation::validate_question;
+    use serde::Deserialize;
+    use std::path::Path;
+
+    /// Response of the Open Trivia DB API (`api.php`), saved to a file
+    #[derive(Deserialize)]
+    struct OpenTriviaResponse {
+        results: Vec<OpenTriviaQuestion>,
+    }
+
+    #[derive(Deserialize)]
+    struct OpenTriviaQuestion {
+        #[serde(rename = "type")]
+        kind: String,
+        category: String,
+        question: String,
+        correct_answer: String,
+        #[serde(default)]
+        incorrect_answers: Vec<String>,
+    }
+
+    /// English category names used by Open Trivia DB and our own categories they match.
+    /// Categories not listed are used as they are, in lower case.
+    const CATEGORY_MAP: &[(&str, &str)] = &[
+        ("general knowledge", "yleistieto"),
+        ("books", "kirjallisuus"),
+        ("film", "elokuva"),
+        ("music", "musiikki"),
+        ("musicals & theatres", "kulttuuri"),
+        ("television", "pop-kulttuuri"),
+        ("video games", "pop-kulttuuri"),
+        ("board games", "pop-kulttuuri"),
+        ("comics", "pop-kulttuuri"),
+        ("japanese anime & manga", "pop-kulttuuri"),
+        ("cartoon & animations", "pop-kulttuuri"),
+        ("science & nature", "luonto"),
+        ("computers", "tietotekniikka"),
+        ("gadgets", "tietotekniikka"),
+        ("mathematics", "matematiikka"),
+        ("mythology", "mytologia"),
+        ("sports", "urheilu"),
+        ("geography", "maantieto"),
+        ("history", "historia"),
+        ("politics", "politiikka"),
+        ("art", "taide"),
+        ("celebrities", "henkilöt"),
+        ("animals", "eläinkunta"),
+        ("vehicles", "ajoneuvot"),
+    ];
+
+    /// Entities seen in Open Trivia DB and Moodle exports, numeric ones are decoded too
+    const HTML_ENTITIES: &[(&str, &str)] = &[
+        ("amp", "&"),
+        ("quot", "\""),
+        ("apos", "'"),
+        ("lt", "<"),
+        ("gt", ">"),
+        ("nbsp", " "),
+        ("shy", ""),
+        ("lrm", ""),
+        ("rlm", ""),
+        ("hellip", "…"),
+        ("ndash", "–"),
+        ("mdash", "—"),
+        ("lsquo", "‘"),
+        ("rsquo", "’"),
+        ("ldquo", "“"),
+        ("rdquo", "”"),
+        ("laquo", "«"),
+        ("raquo", "»"),
+        ("deg", "°"),
+        ("pi", "π"),
+        ("sup2", "²"),
+        ("sup3", "³"),
+        ("times", "×"),
+        ("divide", "÷"),
+        ("euro", "€"),
+        ("pound", "£"),
+        ("eacute", "é"),
+        ("Eacute", "É"),
+        ("egrave", "è"),
+        ("aacute", "á"),
+        ("Aacute", "Á"),
+        ("iacute", "í"),
+        ("oacute", "ó"),
+        ("Oacute", "Ó"),
+        ("uacute", "ú"),
+        ("ntilde", "ñ"),
+        ("ccedil", "ç"),
+        ("auml", "ä"),
+        ("Auml", "Ä"),
+        ("ouml", "ö"),
+        ("Ouml", "Ö"),
+        ("uuml", "ü"),
+        ("Uuml", "Ü"),
+        ("aring", "å"),
+        ("Aring", "Å"),
+        ("oslash", "ø"),
+        ("Oslash", "Ø"),
+        ("aelig", "æ"),
+        ("szlig", "ß"),
+    ];
+
+    pub fn map_category(category: &str) -> String {
+        // "Entertainment: Music" and "Science: Computers" are mapped by their last part
+        let category = category
+            .rsplit(':')
+            .next()
+            .unwrap_or(category)
+            .trim()
+            .to_lowercase();
+        match CATEGORY_MAP.iter().find(|(source, _)| *source == category) {
+            Some((_, ours)) => ours.to_string(),
+            None => category,
+        }
+    }
+
+    pub fn decode_html_entities(text: &str) -> String {
+        let mut decoded = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('&') {
+            decoded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let entity = rest[1..]
+                .find(';')
+                .filter(|end| *end <= 10)
+                .map(|end| &rest[1..end + 1]);
+            let replacement = entity.and_then(|entity| match entity.strip_prefix('#') {
+                Some(number) => {
+                    let code = match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => number.parse::<u32>().ok(),
+                    };
+                    code.and_then(char::from_u32).map(|c| c.to_string())
+                }
+                None => HTML_ENTITIES
+                    .iter()
+                    .find(|(name, _)| *name == entity)
+                    .map(|(_, text)| text.to_string()),
+            });
+            match (entity, replacement) {
+                (Some(entity), Some(replacement)) => {
+                    decoded.push_str(&replacement);
+                    rest = &rest[entity.len() + 2..];
+                }
+                _ => {
+                    decoded.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        decoded.push_str(rest);
+        decoded
+    }
+
+    fn strip_html_tags(text: &str) -> String {
+        let mut stripped = String::with_capacity(text.len());
+        let mut in_tag = false;
+        for c in text.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' if in_tag => in_tag = false,
+                c if !in_tag => stripped.push(c),
+                _ => (),
+            }
+        }
+        stripped
+    }
+
+    pub fn decode_base64(text: &str) -> Option<String> {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut bytes: Vec<u8> = vec![];
+        let mut buffer: u32 = 0;
+        let mut bits = 0;
+        for c in text.trim().trim_end_matches('=').bytes() {
+            let value = ALPHABET.iter().position(|letter| *letter == c)? as u32;
+            buffer = (buffer << 6) | value;
+            bits += 6;
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push((buffer >> bits) as u8);
+                buffer &= (1 << bits) - 1;
+            }
+        }
+        String::from_utf8(bytes).ok()
+    }
+
+    /// Questions from an Open Trivia DB response. Both the default HTML encoded and
+    /// `encode=base64` responses are understood.
+    pub fn from_open_trivia_db(data: &str, first_id: i64) -> Result<Vec<Question>, Vec<String>> {
+        let response = match serde_json::from_str::<OpenTriviaResponse>(data) {
+            Ok(response) => response,
+            Err(error) => {
+                return Err(vec![format!("Not an Open Trivia DB response: {}", error)]);
+            }
+        };
+
+        let mut questions: Vec<Question> = vec![];
+        let mut problems: Vec<String> = vec![];
+        for (index, result) in response.results.into_iter().enumerate() {
+            let base64 = !["multiple", "boolean"].contains(&result.kind.as_str());
+            // Trimmed so that the answer is read out exactly as shown
+            let decode = |text: &str| -> Result<String, String> {
+                let decoded = match base64 {
+                    true => decode_base64(text).ok_or(format!("{} is not valid base64", text))?,
+                    false => decode_html_entities(text),
+                };
+                Ok(decoded.trim().to_string())
+            };
+            let decoded = (|| -> Result<RawQuestion, String> {
+                let kind = decode(&result.kind)?;
+                let correct = decode(&result.correct_answer)?;
+                let mut raw = RawQuestion {
+                    id: first_id + questions.len() as i64,
+                    category: vec![map_category(&decode(&result.category)?)],
+                    question: decode(&result.question)?,
+                    ..Default::default()
+                };
+                match kind.as_str() {
+                    "boolean" => {
+                        raw.kind = Some("true_false".to_string());
+                        raw.correct = Some(serde_json::Value::Bool(
+                            correct.eq_ignore_ascii_case("true"),
+                        ));
+                    }
+                    "multiple" => {
+                        raw.correct = Some(serde_json::Value::String(correct));
+                        for incorrect in result.incorrect_answers.iter() {
+                            raw.incorrect.push(decode(incorrect)?);
+                        }
+                    }
+                    other => return Err(format!("unknown question type {}", other)),
+                }
+                Ok(raw)
+            })();
+            match decoded.and_then(Question::try_from) {
+                Ok(mut question) => {
+                    question.fill_missing_tts();
+                    questions.push(question);
+                }
+                Err(error) => problems.push(format!("Result {}: {}", index + 1, error)),
+            }
+        }
+
+        match problems.is_empty() {
+            true => Ok(questions),
+            false => Err(problems),
+        }
+    }
+
+    /// Split at characters GIFT uses for markup, skipping ones escaped with a backslash.
+    /// Each part starts with the separator that began it.
+    fn split_unescaped(text: &str, separators: &[char]) -> Vec<String> {
+        let mut parts: Vec<String> = vec![String::new()];
+        let mut escaped = false;
+        for c in text.chars() {
+            if !escaped && separators.contains(&c) {
+                parts.push(String::new());
+            }
+            if let Some(part) = parts.last_mut() {
+                part.push(c);
+            }
+            escaped = !escaped && c == '\\';
+        }
+        parts
+    }
+
+    fn find_unescaped(text: &str, wanted: char) -> Option<usize> {
+        let mut escaped = false;
+        for (index, c) in text.char_indices() {
+            if !escaped && c == wanted {
+                return Some(index);
+            }
+            escaped = !escaped && c == '\\';
+        }
+        None
+    }
+
+    fn unescape_gift(text: &str, html: bool) -> String {
+        let mut unescaped = String::with_capacity(text.len());
+        let mut escaped = false;
+        for c in text.chars() {
+            match (escaped, c) {
+                (false, '\\') => escaped = true,
+                (true, 'n') => {
+                    unescaped.push('\n');
+                    escaped = false;
+                }
+                (_, c) => {
+                    unescaped.push(c);
+                    escaped = false;
+                }
+            }
+        }
+        let unescaped = match html {
+            true => decode_html_entities(&strip_html_tags(&unescaped)),
+            false => unescaped,
+        };
+        unescaped
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    /// Question of one GIFT block, without ID and category
+    fn gift_question(block: &str) -> Result<RawQuestion, String> {
+        let mut text = block.trim();
+        // ::Title:: is only for Moodle's question list
+        if let Some(titled) = text.strip_prefix("::") {
+            match titled.find("::") {
+                Some(end) => text = titled[end + 2..].trim_start(),
+                None => return Err("title is not closed with ::".to_string()),
+            }
+        }
+        let mut html = false;
+        if let Some(format_end) = text.strip_prefix('[').and_then(|rest| rest.find(']')) {
+            html = &text[1..format_end + 1] == "html";
+            text = text[format_end + 2..].trim_start();
+        }
+
+        let open = find_unescaped(text, '{').ok_or("answers in {} are missing".to_string())?;
+        let close = open
+            + find_unescaped(&text[open..], '}')
+                .ok_or("answers are not closed with }".to_string())?;
+        let before = unescape_gift(&text[..open], html);
+        let after = unescape_gift(&text[close + 1..], html);
+        let answers = text[open + 1..close].trim();
+
+        let mut raw = RawQuestion {
+            // Missing word format has text after the answers
+            question: match after.chars().next() {
+                None => before,
+                Some(c) if c.is_ascii_punctuation() => format!("{} _____{}", before, after),
+                Some(_) => format!("{} _____ {}", before, after),
+            },
+            ..Default::default()
+        };
+
+        // Feedback after # is not shown anywhere
+        let without_feedback = |answer: &str| split_unescaped(answer, &['#']).swap_remove(0);
+
+        if let Some(numeric) = answers.strip_prefix('#') {
+            // Only the first of several accepted answers is used
+            let first = split_unescaped(numeric, &['='])
+                .into_iter()
+                .find(|answer| !answer.trim().is_empty())
+                .unwrap_or_default();
+            let numeric = without_feedback(first.trim());
+            let numeric = numeric.trim_start_matches('=');
+            let (correct, tolerance) = match (numeric.split_once(".."), numeric.split_once(':')) {
+                (Some((low, high)), _) => {
+                    let low: f64 = low
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("{} is not a number", low))?;
+                    let high: f64 = high
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("{} is not a number", high))?;
+                    ((low + high) / 2.0, (high - low).abs() / 2.0)
+                }
+                (None, Some((correct, tolerance))) => (
+                    correct
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("{} is not a number", correct))?,
+                    tolerance
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("{} is not a number", tolerance))?,
+                ),
+                (None, None) => (
+                    numeric
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("{} is not a number", numeric))?,
+                    0.0,
+                ),
+            };
+            raw.kind = Some("numeric".to_string());
+            raw.correct = Some(serde_json::json!(correct));
+            raw.tolerance = Some(tolerance);
+            return Ok(raw);
+        }
+
+        match without_feedback(answers).trim().to_uppercase().as_str() {
+            "T" | "TRUE" => {
+                raw.kind = Some("true_false".to_string());
+                raw.correct = Some(serde_json::Value::Bool(true));
+                return Ok(raw);
+            }
+            "F" | "FALSE" => {
+                raw.kind = Some("true_false".to_string());
+                raw.correct = Some(serde_json::Value::Bool(false));
+                return Ok(raw);
+            }
+            "" => return Err("essay questions are not supported".to_string()),
+            _ => (),
+        }
+        if answers.contains("->") {
+            return Err("matching questions are not supported".to_string());
+        }
+
+        let mut correct: Vec<String> = vec![];
+        let mut incorrect: Vec<String> = vec![];
+        for answer in split_unescaped(answers, &['=', '~']).into_iter().skip(1) {
+            let is_correct = answer.starts_with('=');
+            let answer = without_feedback(&answer[1..]);
+            let mut answer = answer.trim();
+            // ~%100%answer is correct, other weights are partial credit we can't give
+            let mut weight = match is_correct {
+                true => 100.0,
+                false => 0.0,
+            };
+            if let Some(weighted) = answer.strip_prefix('%') {
+                if let Some((percent, rest)) = weighted.split_once('%') {
+                    weight = percent.trim().parse().unwrap_or(0.0);
+                    answer = rest.trim();
+                }
+            }
+            match weight >= 100.0 {
+                true => correct.push(unescape_gift(answer, html)),
+                false => incorrect.push(unescape_gift(answer, html)),
+            }
+        }
+
+        match (correct.len(), incorrect.len()) {
+            (0, _) if answers.contains('%') => {
+                Err("answers with partial credit are not supported".to_string())
+            }
+            (0, _) => Err("no correct answer".to_string()),
+            // Only correct answers means players type the answer
+            (_, 0) => {
+                raw.kind = Some("free_text".to_string());
+                raw.correct = Some(serde_json::Value::String(correct.remove(0)));
+                raw.alternatives = correct;
+                Ok(raw)
+            }
+            (1, _) => {
+                raw.correct = Some(serde_json::Value::String(correct.remove(0)));
+                raw.incorrect = incorrect;
+                Ok(raw)
+            }
+            _ => Err("questions with several correct options are not supported".to_string()),
+        }
+    }
+
+    /// Questions in Moodle GIFT format. `$CATEGORY:` lines set category of the questions
+    /// after them, questions without one get `category`.
+    pub fn from_gift(
+        data: &str,
+        first_id: i64,
+        category: &str,
+    ) -> Result<Vec<Question>, Vec<String>> {
+        let mut questions: Vec<Question> = vec![];
+        let mut problems: Vec<String> = vec![];
+        let mut current_category = category.to_string();
+        let mut block = String::new();
+        let mut block_starts_on = 0;
+
+        let lines = data
+            .trim_start_matches('\u{feff}')
+            .lines()
+            .map(Some)
+            .chain(std::iter::once(None));
+        for (index, line) in lines.enumerate() {
+            let trimmed = line.map(|line| line.trim()).unwrap_or_default();
+            if trimmed.starts_with("//") {
+                continue;
+            }
+            if let Some(path) = trimmed.strip_prefix("$CATEGORY:") {
+                // $course$/top/Musiikki is category Musiikki
+                current_category = map_category(path.trim().rsplit('/').next().unwrap_or(path));
+                continue;
+            }
+            if !trimmed.is_empty() {
+                if block.is_empty() {
+                    block_starts_on = index + 1;
+                }
+                block.push_str(trimmed);
+                block.push('\n');
+                continue;
+            }
+            if block.is_empty() {
+                continue;
+            }
+
+            match gift_question(&block) {
+                Ok(mut raw) => {
+                    raw.id = first_id + questions.len() as i64;
+                    raw.category = vec![current_category.clone()];
+                    match Question::try_from(raw) {
+                        Ok(mut question) => {
+                            question.fill_missing_tts();
+                            questions.push(question);
+                        }
+                        Err(error) => problems.push(format!("Line {}: {}", block_starts_on, error)),
+                    }
+                }
+                Err(error) => problems.push(format!("Line {}: {}", block_starts_on, error)),
+            }
+            block.clear();
+        }
+
+        match problems.is_empty() {
+            true => Ok(questions),
+            false => Err(problems),
+        }
+    }
+
+    /// Import a local file into a pack. Spreadsheets update the pack's questions by ID,
+    /// Open Trivia DB responses (.json) and GIFT files (.gift, .txt) are added after them.
+    /// With `replace` the pack's questions are replaced with the imported ones instead.
+    pub fn import_file(
+        source: &Path,
+        pack_path: &Path,
+        replace: bool,
+    ) -> Result<String, Vec<String>> {
+        let extension = source
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        if ["csv", "tsv", "tab"].contains(&extension.as_str()) {
+            return spreadsheet::import_file(source, pack_path, replace);
+        }
+
+        let data = match std::fs::read_to_string(source) {
+            Ok(data) => data,
+            Err(error) => return Err(vec![format!("{}: {}", source.display(), error)]),
+        };
+        let (mut pack, pack_name) = open_pack(pack_path)?;
+        if replace {
+            pack.questions.clear();
+        }
+        let first_id = first_free_id(&pack.questions);
+
+        let mut imported = match extension.as_str() {
+            "json" => from_open_trivia_db(&data, first_id)?,
+            "gift" | "txt" => from_gift(&data, first_id, &pack_name)?,
+            _ => {
+                return Err(vec![format!(
+                    "{}: unknown file type, expected .csv, .tsv, .json or .gift",
+                    source.display()
+                )]);
+            }
+        };
+
+        let mut problems: Vec<String> = vec![];
+        for question in imported.iter_mut() {
+            question.pack = pack_name.clone();
+            validate_question(question, &pack.metadata.categories, &mut problems);
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        let count = imported.len();
+        pack.questions.extend(imported);
+        save_pack(&pack, pack_path).map_err(|error| vec![error])?;
+        Ok(format!(
+            "Imported {} questions from {} to {}",
+            count,
+            source.display(),
+            pack_path.display()
+        ))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::questions::structure::QuestionKind;
+
+        #[test]
+        fn html_entities_are_decoded() {
+            assert_eq!(
+                decode_html_entities("&quot;Hello&quot; &amp; &#039;bye&#x27; &eacute;"),
+                "\"Hello\" & 'bye' é"
+            );
+            // Unknown entities and lone ampersands are kept as they are
+            assert_eq!(decode_html_entities("R&B &foo; &"), "R&B &foo; &");
+        }
+
+        #[test]
+        fn base64_is_decoded() {
+            assert_eq!(decode_base64("SMOkbWU="), Some("Häme".to_string()));
+            assert_eq!(decode_base64("UGFyaXMg"), Some("Paris ".to_string()));
+            assert_eq!(decode_base64("not base64!"), None);
+        }
+
+        #[test]
+        fn categories_are_mapped_by_last_part() {
+            assert_eq!(map_category("Entertainment: Music"), "musiikki");
+            assert_eq!(map_category("Science: Computers"), "tietotekniikka");
+            assert_eq!(map_category("Astronomy"), "astronomy");
+        }
+
+        #[test]
+        fn open_trivia_db_html_response_is_decoded() {
+            let data = r#"{"response_code": 0, "results": [
+                {"type": "multiple", "difficulty": "easy", "category": "Entertainment: Music",
+                 "question": "Who sang &quot;Hello&quot;?", "correct_answer": "Adele &amp; co",
+                 "incorrect_answers": ["Beyonc&eacute;", "Madonna"]},
+                {"type": "boolean", "difficulty": "easy", "category": "History",
+                 "question": "Is &pi; &gt; 3?", "correct_answer": "True", "incorrect_answers": ["False"]}
+            ]}"#;
+            let questions = from_open_trivia_db(data, 5).unwrap();
+            assert_eq!(questions[0].id, 5);
+            assert_eq!(questions[0].category, vec!["musiikki".to_string()]);
+            assert_eq!(questions[0].question, "Who sang \"Hello\"?");
+            assert_eq!(questions[0].question_tts, questions[0].question);
+            assert_eq!(questions[0].correct, "Adele & co");
+            assert_eq!(questions[0].correct_tts, "Oikea vastaus on Adele & co.");
+            assert_eq!(
+                questions[0].kind,
+                QuestionKind::MultipleChoice {
+                    correct: "Adele & co".to_string(),
+                    incorrect: vec!["Beyoncé".to_string(), "Madonna".to_string()],
+                }
+            );
+            assert_eq!(questions[1].id, 6);
+            assert_eq!(questions[1].kind, QuestionKind::TrueFalse { correct: true });
+            assert_eq!(questions[1].correct_tts, "Oikea vastaus on Totta.");
+        }
+
+        #[test]
+        fn open_trivia_db_base64_response_is_decoded() {
+            let data = r#"{"response_code": 0, "results": [
+                {"type": "bXVsdGlwbGU=", "difficulty": "ZWFzeQ==", "category": "R2VvZ3JhcGh5",
+                 "question": "V2hhdD8=", "correct_answer": "UGFyaXMg",
+                 "incorrect_answers": ["TG9uZG9u", "Um9tZQ=="]}
+            ]}"#;
+            let questions = from_open_trivia_db(data, 0).unwrap();
+            assert_eq!(questions[0].category, vec!["maantieto".to_string()]);
+            assert_eq!(questions[0].question, "What?");
+            assert_eq!(questions[0].correct, "Paris");
+            assert_eq!(questions[0].correct_tts, "Oikea vastaus on Paris.");
+        }
+
+        #[test]
+        fn gift_questions_of_each_type_are_read() {
+            let data = "// Kommentti\n\
+                        $CATEGORY: $course$/top/Musiikki\n\
+                        ::Q1:: Kuka sävelsi Finlandian? {=Sibelius#Hienoa ~Mozart ~Bach}\n\
+                        \n\
+                        [html]Onko <b>Helsinki</b> pääkaupunki? {T}\n\
+                        \n\
+                        Suomen pääkaupunki on {=Helsinki =Helsingfors}.\n\
+                        \n\
+                        Minä vuonna Suomi itsenäistyi? {#=1917:0#Oikein =1918:1}\n\
+                        \n\
+                        Montako? {#1..5}\n";
+            let questions = from_gift(data, 10, "yleistieto").unwrap();
+            assert_eq!(questions.len(), 5);
+            assert_eq!(questions[0].id, 10);
+            assert_eq!(questions[0].category, vec!["musiikki".to_string()]);
+            assert_eq!(
+                questions[0].kind,
+                QuestionKind::MultipleChoice {
+                    correct: "Sibelius".to_string(),
+                    incorrect: vec!["Mozart".to_string(), "Bach".to_string()],
+                }
+            );
+            assert_eq!(questions[1].question, "Onko Helsinki pääkaupunki?");
+            assert_eq!(questions[1].kind, QuestionKind::TrueFalse { correct: true });
+            assert_eq!(questions[2].question, "Suomen pääkaupunki on _____.");
+            assert_eq!(
+                questions[2].kind,
+                QuestionKind::FreeText {
+                    correct: "Helsinki".to_string(),
+                    alternatives: vec!["Helsingfors".to_string()],
+                    max_typos: None,
+                }
+            );
+            assert_eq!(
+                questions[3].kind,
+                QuestionKind::Numeric {
+                    correct: 1917.0,
+                    tolerance: 0.0,
+                    scoring: Default::default(),
+                }
+            );
+            assert_eq!(questions[3].correct_tts, "Oikea vastaus on 1917.");
+            assert_eq!(
+                questions[4].kind,
+                QuestionKind::Numeric {
+                    correct: 3.0,
+                    tolerance: 2.0,
+                    scoring: Default::default(),
+                }
+            );
+        }
+
+        #[test]
+        fn gift_problems_are_reported_by_line() {
+            let data = "Essee? {}\n\nAvoin? {=A\n";
+            assert_eq!(
+                from_gift(data, 0, "yleistieto"),
+                Err(vec![
+                    "Line 1: essay questions are not supported".to_string(),
+                    "Line 3: answers are not closed with }".to_string(),
+                ])
+            );
+        }
+    }
+}
+
+pub mod selection {
+    use super::structure::{Metadata, Question, QuestionBank, QuestionId};
+    use rand::seq::IndexedRandom;
+    use serde::{Deserialize, Serialize};
+
+    /// Picks the next question among the ones still available for this game
+    pub trait SelectionStrategy {
+        /// Returns the question and the category it was picked for, if any.
+        /// `previous_category` is what the previous question of this game was picked for.
+        fn select<'a>(
+            &self,
+            candidates: &[&'a Question],
+            question_number: u64,
+            previous_category: Option<&str>,
+        ) -> Option<(&'a Question, Option<String>)>;
+    }
+
+    /// Uniformly random, ignores categories
+    pub struct RandomSelection;
+
+    /// Cycles through categories in alphabetical order
+    pub struct RoundRobinSelection;
+
+    /// Consecutive runs of `round_length` questions share a category
+    pub struct ThemedRoundsSelection {
+        pub round_length: u64,
+    }
+
+    /// Selection strategy as stored in game settings
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(tag = "mode", rename_all = "snake_case")]
+    pub enum SelectionMode {
+        #[default]
+        Random,
+        RoundRobin,
+        ThemedRounds {
+            round_length: u64,
+        },
+    }
+
+    impl SelectionMode {
+        pub fn strategy(&self) -> Box<dyn SelectionStrategy> {
+            match self {
+                SelectionMode::Random => Box::new(RandomSelection),
+                SelectionMode::RoundRobin => Box::new(RoundRobinSelection),
+                SelectionMode::ThemedRounds { round_length } => Box::new(ThemedRoundsSelection {
+                    round_length: *round_length,
+                }),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct CategoryCount {
+        pub category: String,
+        pub total: u64,
+        pub available: u64,
+    }
+
+    /// Whether question passes category filters. Empty `only` list allows every category.
+    pub fn question_allowed(question: &Question, only: &[String], exclude: &[String]) -> bool {
+        let included = only.is_empty()
+            || question
+                .category
+                .iter()
+                .any(|category| only.contains(category));
+        let excluded = question
+            .category
+            .iter()
+            .any(|category| exclude.contains(category));
+        included && !excluded
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct PackCount {
+        pub name: String,
+        pub metadata: Metadata,
+        pub total: u64,
+        pub available: u64,
+    }
+
+    /// Whether question belongs to one of the packs in play. Empty list allows every pack.
+    pub fn pack_allowed(question: &Question, packs: &[String]) -> bool {
+        packs.is_empty() || packs.contains(&question.pack)
+    }
+
+    /// All packs in the bank with total and not yet asked question counts
+    pub fn list_packs(all_questions: &QuestionBank, asked_ids: &[QuestionId]) -> Vec<PackCount> {
+        all_questions
+            .packs
+            .iter()
+            .map(|pack| {
+                let in_pack: Vec<&Question> = all_questions
+                    .questions
+                    .iter()
+                    .filter(|question| question.pack == pack.name)
+                    .collect();
+                PackCount {
+                    name: pack.name.clone(),
+                    metadata: pack.metadata.clone(),
+                    total: in_pack.len() as u64,
+                    available: in_pack
+                        .iter()
+                        .filter(|question| !asked_ids.contains(&question.qualified_id()))
+                        .count() as u64,
+                }
+            })
+            .collect()
+    }
+
+    /// All categories in the packs in play with total and not yet asked question counts
+    pub fn list_categories(
+        all_questions: &QuestionBank,
+        packs: &[String],
+        asked_ids: &[QuestionId],
+    ) -> Vec<CategoryCount> {
+        let mut result: Vec<CategoryCount> = vec![];
+        for question in all_questions
+            .questions
+            .iter()
+            .filter(|question| pack_allowed(question, packs))
+        {
+            let asked = asked_ids.contains(&question.qualified_id());
+            for category in question.category.iter() {
+                let index = match result.iter().position(|count| count.category == *category) {
+                    Some(index) => index,
+                    None => {
+                        result.push(CategoryCount {
+                            category: category.clone(),
+                            total: 0,
+                            available: 0,
+                        });
+                        result.len() - 1
+                    }
+                };
+                result[index].total += 1;
+                if !asked {
+                    result[index].available += 1;
+                }
+            }
+        }
+        result.sort_by(|a, b| a.category.cmp(&b.category));
+        result
+    }
+
+    /// Sorted, deduplicated categories of the candidates
+    fn candidate_categories(candidates: &[&Question]) -> Vec<String> {
+        let mut categories: Vec<String> = candidates
+            .iter()
+            .flat_map(|question| question.category.iter().cloned())
+            .collect();
+        categories.sort();
+        categories.dedup();
+        categories
+    }
+
+    fn select_from_category<'a>(
+        candidates: &[&'a Question],
+        category: &str,
+    ) -> Option<(&'a Question, Option<String>)> {
+        let in_category: Vec<&'a Question> = candidates
+            .iter()
+            .filter(|question| question.category.iter().any(|c| c == category))
+            .copied()
+            .collect();
+        in_category
+            .choose(&mut rand::rng())
+            .map(|question| (*question, Some(category.to_string())))
+    }
+
+    impl SelectionStrategy for RandomSelection {
+        fn select<'a>(
+            &self,
+            candidates: &[&'a Question],
+            _question_number: u64,
+            _previous_category: Option<&str>,
+        ) -> Option<(&'a Question, Option<String>)> {
+            candidates
+                .choose(&mut rand::rng())
+                .map(|question| (*question, None))
+        }
+    }
+
+    impl SelectionStrategy for RoundRobinSelection {
+        fn select<'a>(
+            &self,
+            candidates: &[&'a Question],
+            question_number: u64,
+            previous_category: Option<&str>,
+        ) -> Option<(&'a Question, Option<String>)> {
+            let categories = candidate_categories(candidates);
+            // First category after the previous one, wrapping around
+            let next = match previous_category {
+                Some(previous) => categories
+                    .iter()
+                    .find(|category| category.as_str() > previous)
+                    .or(categories.first()),
+                None => categories.first(),
+            };
+            match next {
+                Some(category) => select_from_category(candidates, category),
+                None => RandomSelection.select(candidates, question_number, None),
+            }
+        }
+    }
+
+    impl SelectionStrategy for ThemedRoundsSelection {
+        fn select<'a>(
+            &self,
+            candidates: &[&'a Question],
+            question_number: u64,
+            previous_category: Option<&str>,
+        ) -> Option<(&'a Question, Option<String>)> {
+            let round_length = self.round_length.max(1);
+            let new_round = question_number
+                .saturating_sub(1)
+                .is_multiple_of(round_length);
+
+            // Keep going with the current theme while it has questions left
+            if !new_round {
+                if let Some(previous) = previous_category {
+                    if let Some(selected) = select_from_category(candidates, previous) {
+                        return Some(selected);
+                    }
+                }
+            }
+
+            // Start a new theme, preferring one that can fill a whole round
+            let categories: Vec<String> = candidate_categories(candidates)
+                .into_iter()
+                .filter(|category| Some(category.as_str()) != previous_category)
+                .collect();
+            let full_rounds: Vec<&String> = categories
+                .iter()
+                .filter(|category| {
+                    candidates
+                        .iter()
+                        .filter(|question| question.category.contains(category))
+                        .count() as u64
+                        >= round_length
+                })
+                .collect();
+            let theme = match full_rounds.choose(&mut rand::rng()) {
+                Some(category) => Some(*category),
+                None => categories.choose(&mut rand::rng()),
+            };
+            match theme {
+                Some(category) => select_from_category(candidates, category),
+                None => RandomSelection.select(candidates, question_number, None),
+            }
+        }
+    }
+}
+
+pub mod history {
+    use super::structure::QuestionId;
+    use serde::{Deserialize, Serialize};
+    use std::fs::File;
+    use std::io::Write;
+
+    /// Pack which the questions of the single `questions.json` were moved to. History written
+    /// before question packs refers to it.
+    pub const LEGACY_PACK: &str = "yleinen";
+
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct HistoryEntry {
+        /// Entries written before question packs have no pack, they get LEGACY_PACK on load
+        #[serde(default)]
+        pub pack: String,
+        pub question_id: i64,
+        /// Unix time in seconds
+        pub asked_at: u64,
+    }
+
+    /// Questions asked across games and server restarts, persisted as JSON
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct QuestionHistory {
+        pub entries: Vec<HistoryEntry>,
+        #[serde(skip)]
+        pub path: String,
+    }
+
+    impl QuestionHistory {
+        /// Question IDs asked less than `repeat_after_days` days before `now`
+        pub fn recently_asked(&self, now: u64, repeat_after_days: u64) -> Vec<QuestionId> {
+            let window = repeat_after_days * 24 * 60 * 60;
+            self.entries
+                .iter()
+                .filter(|entry| entry.asked_at + window > now)
+                .map(|entry| QuestionId {
+                    pack: entry.pack.clone(),
+                    id: entry.question_id,
+                })
+                .collect()
+        }
+
+        pub fn record(&mut self, question_id: &QuestionId, now: u64) {
+            self.entries.push(HistoryEntry {
+                pack: question_id.pack.clone(),
+                question_id: question_id.id,
+                asked_at: now,
+            });
+            if let Err(error) = self.save() {
+                eprintln!("Unable to save question history ({}): {}", self.path, error);
+            }
+        }
+
+        fn save(&self) -> Result<(), String> {
+            let data = match serde_json::to_string_pretty(self) {
+                Ok(data) => data,
+                Err(error) => {
+                    return Err(format!("{}", error));
+                }
+            };
+            let mut file = match File::create(&self.path) {
+                Ok(file) => file,
+                Err(error) => {
+                    return Err(format!("{}", error));
+                }
+            };
+            match file.write_all(data.as_bytes()) {
+                Ok(_) => Ok(()),
+                Err(error) => Err(format!("{}", error)),
+            }
+        }
+    }
+
+    pub fn load_history_from_file(path: &str) -> Option<QuestionHistory> {
+        let data = match std::fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(_e) => {
+                println!("Question history ({}) was not found, starting fresh.", path);
+                return Some(QuestionHistory {
+                    entries: vec![],
+                    path: path.to_string(),
+                });
+            }
+        };
+
+        match serde_json::from_str::<QuestionHistory>(&data) {
+            Ok(mut history) => {
+                history.path = path.to_string();
+                for entry in history.entries.iter_mut() {
+                    if entry.pack.is_empty() {
+                        entry.pack = LEGACY_PACK.to_string();
+                    }
+                }
+                Some(history)
+            }
+            Err(e) => {
+                eprintln!("Question history ({}) is invalid.\n\nError: {}", path, e);
+                None
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn entries_without_pack_belong_to_legacy_pack() {
+            let path = std::env::temp_dir()
+                .join(format!("pubiq-history-test-{}.json", std::process::id()));
+            let data = r#"{"entries": [
+                {"question_id": 17, "asked_at": 100},
+                {"pack": "musiikki", "question_id": 17, "asked_at": 100}
+            ]}"#;
+            std::fs::write(&path, data).unwrap();
+            let history = load_history_from_file(&path.to_string_lossy()).unwrap();
+            let _ = std::fs::remove_file(&path);
+            assert_eq!(
+                history.recently_asked(200, 1),
+                vec![
+                    QuestionId {
+                        pack: "yleinen".to_string(),
+                        id: 17
+                    },
+                    QuestionId {
+                        pack: "musiikki".to_string(),
+                        id: 17
+                    },
+                ]
+            );
+        }
+    }
 }
@@ -1,22 +1,109 @@
 pub mod rest_http {
-    use rouille::{post_input, router, try_or_400};
+    use rouille::{post_input, router, try_or_400, websocket};
     use serde_json::json;
-    use std::sync::{Arc, Mutex};
+    use std::sync::{mpsc::Receiver, Arc, Mutex};
+    use std::thread;
     use uuid::Uuid;
 
     use crate::{
-        game::state::{Answers, GameStage, GameState, Player, QuestionStage, Questions},
-        helpers::time_helpers::uptime_ms,
+        game::{
+            controller::{count_available_questions, unavailable_question_ids},
+            events::GameEvent,
+            scoring::ScoringMode,
+            settings::TeamMode,
+            state::{Answers, GameStage, GameState, Player, QuestionStage, Questions},
+        },
+        helpers::{secrets::secrets_match, time_helpers::uptime_ms},
+        questions::{
+            answers::normalize_text,
+            bank::BankHandle,
+            history::QuestionHistory,
+            selection::{list_categories, list_packs, SelectionMode},
+            structure::QuestionBank,
+        },
+        rooms::registry::{Room, RoomRegistry},
     };
 
-    pub fn run_rest_http_api(
-        game_state: Arc<Mutex<GameState>>,
-        _questions: Arc<Mutex<Vec<Questions>>>,
-        answers: Arc<Mutex<Vec<Answers>>>,
-    ) {
+    /// Who is listening on the other end of a state WebSocket
+    enum Subscriber {
+        Presenter,
+        Player(Uuid),
+    }
+
+    struct StateSocket {
+        room: Arc<Room>,
+        subscriber: Subscriber,
+        websocket: websocket::Websocket,
+    }
+
+    fn json_response(body: String) -> rouille::Response {
+        rouille::Response::text(body).with_additional_header("Content-Type", "application/json")
+    }
+
+    fn room_not_found() -> rouille::Response {
+        json_response(json!({"success": false, "error": "Room not found"}).to_string())
+            .with_status_code(404)
+    }
+
+    fn forbidden(error: &str) -> rouille::Response {
+        json_response(json!({"success": false, "error": error}).to_string()).with_status_code(403)
+    }
+
+    fn presenter_only() -> rouille::Response {
+        forbidden("Only the presenter of the room can do this")
+    }
+
+    /// Old endpoints without a join code serve the only open room
+    fn no_single_room() -> rouille::Response {
+        json_response(
+            json!({"success": false, "error": "No single room open, use /room/{code}/ endpoints"})
+                .to_string(),
+        )
+        .with_status_code(404)
+    }
+
+    /// Admin endpoints need `Authorization: Bearer <PUBIQ_ADMIN_TOKEN>`. Without the
+    /// environment variable they are not available at all.
+    fn admin_authorized(request: &rouille::Request, admin_token: &Option<String>) -> bool {
+        let admin_token = match admin_token {
+            Some(admin_token) => admin_token,
+            None => return false,
+        };
+        match request
+            .header("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+        {
+            Some(token) => secrets_match(admin_token, token.trim()),
+            None => false,
+        }
+    }
+
+    pub fn run_rest_http_api(rooms: Arc<RoomRegistry>, state_changed_rx: Receiver<String>) {
         let bind_address = "0.0.0.0:80";
         println!("REST API listening on {}", bind_address);
 
+        let admin_token = std::env::var("PUBIQ_ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.trim().is_empty());
+        if admin_token.is_none() {
+            println!("Admin endpoints are disabled, set PUBIQ_ADMIN_TOKEN to use them");
+        }
+
+        let sockets: Arc<Mutex<Vec<StateSocket>>> = Arc::new(Mutex::new(vec![]));
+
+        // Start state pusher
+        let rooms_clone = rooms.clone();
+        let sockets_clone = sockets.clone();
+        let builder = thread::Builder::new().name("State pusher".into());
+        if builder
+            .spawn(move || {
+                run_state_pusher(rooms_clone, sockets_clone, state_changed_rx);
+            })
+            .is_err()
+        {
+            eprintln!("Unable to start state pusher, clients have to poll");
+        }
+
         rouille::start_server(bind_address, move |request| {
             // Check if static file is found
             let response = rouille::match_assets(request, "web");
@@ -28,6 +115,10 @@ pub mod rest_http {
                 return response;
             }
 
+            if request.url().starts_with("/admin/") && !admin_authorized(request, &admin_token) {
+                return forbidden("Admin token is missing or wrong");
+            }
+
             // If not, proceed to router
             router!(request,
                 (GET) (/) => {
@@ -41,85 +132,345 @@ pub mod rest_http {
                     .with_additional_header("Content-Type", "application/json")
                 },
 
-                (POST) (/register_player) => {
+                (POST) (/room/create) => {
+                    // Admin may open rooms beyond the limit
+                    match rooms.create_room(admin_authorized(request, &admin_token)) {
+                        Ok(room) => json_response(json!({"success": true, "code": room.code, "secret": room.presenter_secret()}).to_string()),
+                        Err(error) => json_response(json!({"success": false, "error": error}).to_string()),
+                    }
+                },
+
+                (POST) (/admin/reload_questions) => {
+                    json_response(reload_questions(&rooms.question_bank))
+                },
+
+                (POST) (/room/{code: String}/close) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    let presenter = try_or_400!(post_input!(request, {
+                        secret: String,
+                    }));
+                    if !room.is_presenter(&presenter.secret) {
+                        return presenter_only();
+                    }
+                    match rooms.close_room(&code) {
+                        Ok(_) => json_response(json!({"success": true}).to_string()),
+                        Err(error) => json_response(json!({"success": false, "error": error}).to_string()),
+                    }
+                },
+
+                (POST) (/room/{code: String}/register_player) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
                     let player = try_or_400!(post_input!(request, {
                         name: String,
+                        team: Option<String>,
                     }));
 
-                    match register_new_player(&game_state, &player.name) {
+                    match register_new_player(&room.game_state, &player.name, player.team) {
                         Ok(message) => {
-                            return
-                                rouille::Response::text(message)
-                                .with_additional_header("Content-Type", "application/json");
-                        },
-                        Err(message) => {
-                            return
-                                rouille::Response::text(message)
-                                .with_additional_header("Content-Type", "application/json");
-
+                            let _ = room.events_tx.send(GameEvent::PlayerJoined);
+                            json_response(message)
                         },
+                        Err(message) => json_response(message),
                     }
-                    rouille::Response::text("")
                 },
 
-                (GET) (/get_all_players) => {
-                    let s = format!("{}", json!({"success": true, "players": get_all_players(&game_state)}).to_string());
-                    rouille::Response::text(s)
-                        .with_additional_header("Content-Type", "application/json")
+                (GET) (/room/{code: String}/get_all_players) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    json_response(json!({"success": true, "players": get_all_players(&room.game_state), "teams": get_all_teams(&room.game_state)}).to_string())
                 },
 
-                (GET) (/get_player_state/{uuid: String}) => {
-                    let s = format!("{}", get_player_state(&game_state, uuid));
-                    rouille::Response::text(s)
-                        .with_additional_header("Content-Type", "application/json")
+                (GET) (/room/{code: String}/get_player_state/{uuid: String}) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    json_response(get_player_state(&room.game_state, uuid))
                 },
 
-                (GET) (/get_presenter_state/) => {
-                    let s = format!("{}", get_presenter_state(&game_state, &answers));
-                    rouille::Response::text(s)
-                        .with_additional_header("Content-Type", "application/json")
+                (GET) (/room/{code: String}/ws/player/{uuid: String}) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    let uuid = match Uuid::parse_str(&uuid) {
+                        Ok(uuid) => uuid,
+                        Err(_) => return rouille::Response::empty_400(),
+                    };
+                    let (response, websocket) = try_or_400!(websocket::start(request, None::<&str>));
+                    accept_state_socket(&sockets, room, Subscriber::Player(uuid), websocket);
+                    response
+                },
+
+                (GET) (/room/{code: String}/ws/presenter) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    let (response, websocket) = try_or_400!(websocket::start(request, None::<&str>));
+                    accept_state_socket(&sockets, room, Subscriber::Presenter, websocket);
+                    response
+                },
+
+                (GET) (/room/{code: String}/get_presenter_state) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    json_response(get_presenter_state(&room.game_state, &room.answers))
                 },
 
-                (POST) (/submit_answer) => {
+                (POST) (/room/{code: String}/submit_answer) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
                     let answer = try_or_400!(post_input!(request, {
                         uuid: String,
                         answer: String,
                     }));
 
-                    match process_answer_submit(&game_state, &answers, &answer.uuid, &answer.answer) {
+                    match process_answer_submit(&room.game_state, &room.answers, &answer.uuid, &answer.answer) {
                         Ok(response) => {
-                            rouille::Response::text(response)
-                                .with_additional_header("Content-Type", "application/json")
+                            let _ = room.events_tx.send(GameEvent::AnswerSubmitted);
+                            json_response(response)
                         },
-                        Err(response) => {
-                            rouille::Response::text(response)
-                                .with_additional_header("Content-Type", "application/json")
+                        Err(response) => json_response(response),
+                    }
+                },
+
+                (GET) (/room/{code: String}/settings) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    json_response(get_settings(&room.game_state, &room.questions, &rooms.history, &rooms.question_bank.current()))
+                },
+
+                (POST) (/room/{code: String}/settings) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    let settings = try_or_400!(post_input!(request, {
+                        secret: String,
+                        introduction_time_ms: Option<u64>,
+                        answer_time_ms: Option<u64>,
+                        finished_time_ms: Option<u64>,
+                        question_limit: Option<u64>,
+                        categories: Option<String>,
+                        excluded_categories: Option<String>,
+                        packs: Option<String>,
+                        selection: Option<String>,
+                        round_length: Option<u64>,
+                        repeat_after_days: Option<u64>,
+                        scoring: Option<String>,
+                        team_mode: Option<String>,
+                    }));
+                    if !room.is_presenter(&settings.secret) {
+                        return presenter_only();
+                    }
+                    let update = SettingsUpdate {
+                        introduction_time_ms: settings.introduction_time_ms,
+                        answer_time_ms: settings.answer_time_ms,
+                        finished_time_ms: settings.finished_time_ms,
+                        question_limit: settings.question_limit,
+                        categories: settings.categories,
+                        excluded_categories: settings.excluded_categories,
+                        packs: settings.packs,
+                        selection: settings.selection,
+                        round_length: settings.round_length,
+                        repeat_after_days: settings.repeat_after_days,
+                        scoring: settings.scoring,
+                        team_mode: settings.team_mode,
+                    };
+                    match update_settings(&room.game_state, &room.questions, &rooms.history, &rooms.question_bank.current(), update) {
+                        Ok(response) => {
+                            let _ = room.events_tx.send(GameEvent::SettingsChanged);
+                            json_response(response)
                         },
+                        Err(response) => json_response(response),
                     }
                 },
 
-                (POST) (/command) => {
+                (GET) (/room/{code: String}/categories) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    json_response(get_categories(&room.game_state, &room.questions, &rooms.history, &rooms.question_bank.current()))
+                },
+
+                (GET) (/room/{code: String}/packs) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    json_response(get_packs(&room.game_state, &room.questions, &rooms.history, &rooms.question_bank.current()))
+                },
+
+                (POST) (/room/{code: String}/command) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
                     let command = try_or_400!(post_input!(request, {
+                        secret: String,
                         command: String,
                     }));
-                    match handle_presenter_command(&game_state, command.command) {
-                        Ok(_) => {
-                            rouille::Response::text(json!({"success": true}).to_string())
-                                .with_additional_header("Content-Type", "application/json")
+                    if !room.is_presenter(&command.secret) {
+                        return presenter_only();
+                    }
+                    match handle_presenter_command(&room, command.command) {
+                        Ok(_) => json_response(json!({"success": true}).to_string()),
+                        Err(error) => json_response(json!({"success": false, "error": error}).to_string()),
+                    }
+                },
+
+                (POST) (/room/{code: String}/review) => {
+                    let room = match rooms.get_room(&code) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    let review = try_or_400!(post_input!(request, {
+                        secret: String,
+                        answer: String,
+                        decision: String,
+                    }));
+                    if !room.is_presenter(&review.secret) {
+                        return presenter_only();
+                    }
+                    let accept = match review.decision.as_str() {
+                        "accept" => true,
+                        "reject" => false,
+                        _ => return json_response(json!({"success": false, "error": "Decision must be accept or reject"}).to_string()),
+                    };
+                    match review_answer(&room.game_state, &review.answer, accept) {
+                        Ok(message) => {
+                            let _ = room.events_tx.send(GameEvent::AnswerReviewed);
+                            json_response(message)
                         },
-                        Err(error) => {
-                            rouille::Response::text(json!({"success": false, "error": error}).to_string())
-                                .with_additional_header("Content-Type", "application/json")
-                        }
+                        Err(message) => json_response(message),
                     }
                 },
 
+                // Single game endpoints from before rooms, for clients which don't know
+                // about join codes yet
+                (GET) (/get_all_players) => {
+                    let room = match rooms.get_only_room() {
+                        Some(room) => room,
+                        None => return no_single_room(),
+                    };
+                    json_response(json!({"success": true, "players": get_all_players(&room.game_state), "teams": get_all_teams(&room.game_state)}).to_string())
+                },
+
+                (GET) (/get_player_state/{uuid: String}) => {
+                    let player_uuid = match Uuid::parse_str(&uuid) {
+                        Ok(player_uuid) => player_uuid,
+                        Err(_) => return json_response(json!({"success": false, "error": "Invalid UUID provided"}).to_string()),
+                    };
+                    let room = match rooms.find_player_room(player_uuid) {
+                        Some(room) => room,
+                        None => return room_not_found(),
+                    };
+                    json_response(get_player_state(&room.game_state, uuid))
+                },
+
+                (GET) (/get_presenter_state/) => {
+                    let room = match rooms.get_only_room() {
+                        Some(room) => room,
+                        None => return no_single_room(),
+                    };
+                    json_response(get_presenter_state(&room.game_state, &room.answers))
+                },
+
                 _ => rouille::Response::empty_404().
                 with_additional_header("Access-Control-Allow-Origin", "*")
             )
         });
     }
 
+    /// Wait for the WebSocket handshake to finish in the background, send the
+    /// current state right away and register the socket for further pushes.
+    fn accept_state_socket(
+        sockets: &Arc<Mutex<Vec<StateSocket>>>,
+        room: Arc<Room>,
+        subscriber: Subscriber,
+        websocket: Receiver<websocket::Websocket>,
+    ) {
+        let sockets = sockets.clone();
+        thread::spawn(move || {
+            if let Ok(mut websocket) = websocket.recv() {
+                let message = get_state_message(&room, &subscriber);
+                if websocket.send_text(&message).is_err() {
+                    return;
+                }
+                let mut sockets_mutex = match sockets.lock() {
+                    Ok(mutex) => mutex,
+                    Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+                };
+                sockets_mutex.push(StateSocket {
+                    room,
+                    subscriber,
+                    websocket,
+                });
+            }
+        });
+    }
+
+    fn get_state_message(room: &Room, subscriber: &Subscriber) -> String {
+        match subscriber {
+            Subscriber::Presenter => get_presenter_state(&room.game_state, &room.answers),
+            Subscriber::Player(uuid) => get_player_state(&room.game_state, uuid.to_string()),
+        }
+    }
+
+    /// Push presenter and player state to open WebSockets of a room whenever
+    /// its game controller signals a state change. Closed sockets and sockets
+    /// of closed rooms are dropped.
+    fn run_state_pusher(
+        rooms: Arc<RoomRegistry>,
+        sockets: Arc<Mutex<Vec<StateSocket>>>,
+        state_changed_rx: Receiver<String>,
+    ) {
+        while let Ok(code) = state_changed_rx.recv() {
+            // Several changes may have piled up, one push per room covers them all
+            let mut changed_rooms = vec![code];
+            while let Ok(code) = state_changed_rx.try_recv() {
+                if !changed_rooms.contains(&code) {
+                    changed_rooms.push(code);
+                }
+            }
+            for code in changed_rooms.iter() {
+                rooms.touch_room(code);
+            }
+
+            let mut sockets_mutex = match sockets.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+
+            sockets_mutex.retain_mut(|socket| {
+                if !changed_rooms.contains(&socket.room.code) {
+                    return true;
+                }
+                if rooms.get_room(&socket.room.code).is_none() {
+                    return false;
+                }
+                let message = get_state_message(&socket.room, &socket.subscriber);
+                socket.websocket.send_text(&message).is_ok()
+            });
+        }
+    }
+
     fn get_presenter_state(
         game_state: &Arc<Mutex<GameState>>,
         answers: &Arc<Mutex<Vec<Answers>>>,
@@ -134,6 +485,7 @@ pub mod rest_http {
                 return json!({
                     "game_stage": game_state_mutex.game_stage.to_string(),
                     "num_players": game_state_mutex.players.len(),
+                    "error": game_state_mutex.error,
                 })
                 .to_string();
             }
@@ -142,21 +494,31 @@ pub mod rest_http {
                     "game_stage": game_state_mutex.game_stage.to_string(),
                     "num_players": game_state_mutex.players.len(),
                     "audio": game_state_mutex.audio,
+                    "generating": game_state_mutex.generating,
                     "tts_text": game_state_mutex.tts_text,
+                    "audio_prepared": game_state_mutex.audio_prepared,
+                    "audio_to_prepare": game_state_mutex.audio_to_prepare,
                 })
                 .to_string();
             }
             GameStage::GameInProgress => {
                 return json!({
                     "game_stage": game_state_mutex.game_stage.to_string(),
+                    "question_number": game_state_mutex.question_number,
                     "question": game_state_mutex.question.question,
+                    "media": game_state_mutex.question.media.urls(),
                     "question_stage": game_state_mutex.question_stage.to_string(),
                     "question_start_time": game_state_mutex.question_start_time,
+                    "stage_time_remaining": game_state_mutex.stage_time_remaining(),
                     "num_players": game_state_mutex.players.len(),
                     "num_players_answered": count_players_answered_to_question(answers, game_state_mutex.question_number).to_string(),
                     "answer": game_state_mutex.question.correct,
                     "context": game_state_mutex.question.context_information,
                     "audio": game_state_mutex.audio,
+                    "generating": game_state_mutex.generating,
+                    "reveal": game_state_mutex.reveal,
+                    "review": game_state_mutex.review,
+                    "leaderboard": game_state_mutex.scores,
                 }).to_string();
             }
             GameStage::ResultsShow => {
@@ -164,6 +526,7 @@ pub mod rest_http {
                     "game_stage": game_state_mutex.game_stage.to_string(),
                     "num_players": game_state_mutex.players.len(),
                     "audio": game_state_mutex.audio,
+                    "generating": game_state_mutex.generating,
                     "tts_text": game_state_mutex.tts_text,
                     "scores": game_state_mutex.scores,
                 })
@@ -228,7 +591,7 @@ pub mod rest_http {
         }
 
         if game_state_mutex.game_stage != GameStage::GameInProgress
-            && game_state_mutex.question_stage != QuestionStage::QuestionAnswerTime
+            || game_state_mutex.question_stage != QuestionStage::QuestionAnswerTime
         {
             return Err(
                 json!({"success": false, "error": "Answers not accepted at this time"}).to_string(),
@@ -239,6 +602,16 @@ pub mod rest_http {
             return Err(json!({"success": false, "error": "Invalid UUID provided"}).to_string());
         }
 
+        if !game_state_mutex.may_answer(uuid) {
+            return Err(
+                json!({"success": false, "error": "Only team captain can answer"}).to_string(),
+            );
+        }
+
+        if let Err(error) = game_state_mutex.question.validate_answer(answer) {
+            return Err(json!({"success": false, "error": error}).to_string());
+        }
+
         for one_answer in answers_mutex.iter() {
             if one_answer.question_number == game_state_mutex.question_number
                 && one_answer.player_uuid == uuid
@@ -254,6 +627,7 @@ pub mod rest_http {
             question_number: game_state_mutex.question_number,
             player_uuid: uuid,
             answer: answer.clone(),
+            time_ms: uptime_ms().saturating_sub(game_state_mutex.stage_start_time),
         });
 
         dbg!(&answers_mutex);
@@ -287,9 +661,38 @@ pub mod rest_http {
             return json!({"success": false, "error": "Invalid UUID provided"}).to_string();
         }
 
+        // In team mode players see how their team is doing
+        let team = game_state_mutex.team_of(uuid).cloned();
+        let standing = game_state_mutex
+            .scores
+            .iter()
+            .find(|points| match &team {
+                Some(team) => points.team.as_ref() == Some(&team.key),
+                None => points.player_uuid == Some(uuid),
+            })
+            .cloned();
+        let score = match &team {
+            Some(team) => Some(team.score),
+            None => game_state_mutex
+                .players
+                .iter()
+                .find(|player| player.uuid == uuid)
+                .map(|player| player.score),
+        };
+
         match game_state_mutex.game_stage {
+            crate::game::state::GameStage::ResultsShow => {
+                let response = json!({
+                    "success": true,
+                    "game_stage": game_state_mutex.game_stage.to_string(),
+                    "score": score,
+                    "position": standing.as_ref().map(|standing| standing.position),
+                    "num_ranked": game_state_mutex.scores.len(),
+                    "team": team.as_ref().map(|team| team.name.clone()),
+                });
+                response.to_string()
+            },
             crate::game::state::GameStage::WaitingForPlayers |
-            crate::game::state::GameStage::ResultsShow |
             //crate::game::state::GameStage::GameFinished |
             crate::game::state::GameStage::IntroducePlayers => {
                 let response = json!({
@@ -299,17 +702,32 @@ pub mod rest_http {
                 return response.to_string();
             },
             crate::game::state::GameStage::GameInProgress => {
+                // Only known once the correct answer has been revealed
+                let result = game_state_mutex.reveal.as_ref().and_then(|reveal| {
+                    reveal
+                        .player_results
+                        .iter()
+                        .find(|result| result.player_uuid == uuid)
+                });
                 let response = json!({
                     "success": true,
                     "game_stage": game_state_mutex.game_stage.to_string(),
+                    "answer_kind": game_state_mutex.question.answer_kind(),
                     "answer_options": game_state_mutex.question.answer_options,
                     "question_number": game_state_mutex.question_number,
                     "question_stage": game_state_mutex.question_stage.to_string(),
                     "question_start_time": game_state_mutex.question_start_time,
+                    "stage_time_remaining": game_state_mutex.stage_time_remaining(),
+                    "may_answer": game_state_mutex.may_answer(uuid),
+                    "team": team.as_ref().map(|team| team.name.clone()),
+                    "correct": result.map(|result| result.correct),
+                    "score": score,
+                    "position": standing.as_ref().map(|standing| standing.position),
+                    "position_change": standing.as_ref().map(|standing| standing.position_change),
                 });
                 return response.to_string();
             },
-        };
+        }
     }
 
     fn get_all_players(game_state: &Arc<Mutex<GameState>>) -> Vec<String> {
@@ -327,9 +745,36 @@ pub mod rest_http {
         players
     }
 
+    /// Team names with their members, as players registered them
+    fn get_all_teams(game_state: &Arc<Mutex<GameState>>) -> Vec<serde_json::Value> {
+        let game_state_mutex = match game_state.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        let mut teams: Vec<(String, Vec<String>)> = vec![];
+
+        for player in game_state_mutex.players.iter() {
+            let team_name = match &player.team {
+                Some(team_name) => team_name,
+                None => continue,
+            };
+            match teams.iter_mut().find(|team| team.0 == *team_name) {
+                Some(team) => team.1.push(player.name.clone()),
+                None => teams.push((team_name.clone(), vec![player.name.clone()])),
+            }
+        }
+
+        teams
+            .into_iter()
+            .map(|(name, members)| json!({"name": name, "members": members}))
+            .collect()
+    }
+
     fn register_new_player(
         game_state: &Arc<Mutex<GameState>>,
         name: &String,
+        team: Option<String>,
     ) -> Result<String, String> {
         let mut game_state_mutex = match game_state.lock() {
             Ok(mutex) => mutex,
@@ -357,32 +802,337 @@ pub mod rest_http {
             uuid: uuid,
             last_seen: uptime_ms(),
             score: 0,
+            correct_answers: 0,
+            streak: 0,
+            // Blank team name means playing alone
+            team: team
+                .map(|team| team.trim().to_string())
+                .filter(|team| !team.is_empty()),
         });
 
         Ok(json!({"success": true, "uuid": uuid.to_string()}).to_string())
     }
 
-    fn handle_presenter_command(
+    /// Settings sent by presenter, missing fields keep their current value
+    struct SettingsUpdate {
+        introduction_time_ms: Option<u64>,
+        answer_time_ms: Option<u64>,
+        finished_time_ms: Option<u64>,
+        question_limit: Option<u64>,
+        categories: Option<String>,
+        excluded_categories: Option<String>,
+        packs: Option<String>,
+        selection: Option<String>,
+        round_length: Option<u64>,
+        repeat_after_days: Option<u64>,
+        scoring: Option<String>,
+        team_mode: Option<String>,
+    }
+
+    /// Rooms waiting for players see new questions right away, running games when they start next
+    fn reload_questions(question_bank: &BankHandle) -> String {
+        match question_bank.reload() {
+            Ok(bank) => json!({
+                "success": true,
+                "packs": bank.packs.len(),
+                "questions": bank.questions.len(),
+            })
+            .to_string(),
+            Err(problems) => json!({
+                "success": false,
+                "error": "Question packs have problems, previously loaded questions are still in use",
+                "problems": problems,
+            })
+            .to_string(),
+        }
+    }
+
+    /// Split comma separated category or pack list, empty string gives empty list
+    fn parse_category_list(categories: &str) -> Vec<String> {
+        categories
+            .split(',')
+            .map(|category| category.trim().to_string())
+            .filter(|category| !category.is_empty())
+            .collect()
+    }
+
+    fn get_settings(
+        game_state: &Arc<Mutex<GameState>>,
+        questions: &Arc<Mutex<Vec<Questions>>>,
+        history: &Arc<Mutex<QuestionHistory>>,
+        all_questions: &QuestionBank,
+    ) -> String {
+        let game_state_mutex = match game_state.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        let questions_mutex = match questions.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        let history_mutex = match history.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        json!({
+            "success": true,
+            "settings": game_state_mutex.settings,
+            "available_questions": count_available_questions(
+                all_questions,
+                &questions_mutex,
+                &history_mutex,
+                &game_state_mutex.settings,
+            ),
+        })
+        .to_string()
+    }
+
+    fn get_categories(
+        game_state: &Arc<Mutex<GameState>>,
+        questions: &Arc<Mutex<Vec<Questions>>>,
+        history: &Arc<Mutex<QuestionHistory>>,
+        all_questions: &QuestionBank,
+    ) -> String {
+        let game_state_mutex = match game_state.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        let questions_mutex = match questions.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        let history_mutex = match history.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        let unavailable =
+            unavailable_question_ids(&questions_mutex, &history_mutex, &game_state_mutex.settings);
+
+        json!({
+            "success": true,
+            "categories": list_categories(
+                all_questions,
+                &game_state_mutex.settings.packs,
+                &unavailable,
+            ),
+        })
+        .to_string()
+    }
+
+    fn get_packs(
+        game_state: &Arc<Mutex<GameState>>,
+        questions: &Arc<Mutex<Vec<Questions>>>,
+        history: &Arc<Mutex<QuestionHistory>>,
+        all_questions: &QuestionBank,
+    ) -> String {
+        let game_state_mutex = match game_state.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        let questions_mutex = match questions.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        let history_mutex = match history.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        let unavailable =
+            unavailable_question_ids(&questions_mutex, &history_mutex, &game_state_mutex.settings);
+
+        json!({
+            "success": true,
+            "packs": list_packs(all_questions, &unavailable),
+        })
+        .to_string()
+    }
+
+    fn update_settings(
         game_state: &Arc<Mutex<GameState>>,
-        command: String,
+        questions: &Arc<Mutex<Vec<Questions>>>,
+        history: &Arc<Mutex<QuestionHistory>>,
+        all_questions: &QuestionBank,
+        update: SettingsUpdate,
     ) -> Result<String, String> {
         let mut game_state_mutex = match game_state.lock() {
             Ok(mutex) => mutex,
             Err(poisoned_mutex) => poisoned_mutex.into_inner(),
         };
 
-        match command.as_str() {
-            "proceed" => {
-                game_state_mutex.proceed_flag = true;
-                return Ok(command);
+        let questions_mutex = match questions.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        let history_mutex = match history.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        // Settings can only be changed before game starts
+        if game_state_mutex.game_stage != GameStage::WaitingForPlayers {
+            return Err(json!({"success": false, "error": "Game in progress"}).to_string());
+        }
+
+        let mut settings = game_state_mutex.settings.clone();
+        if let Some(introduction_time_ms) = update.introduction_time_ms {
+            settings.introduction_time_ms = introduction_time_ms;
+        }
+        if let Some(answer_time_ms) = update.answer_time_ms {
+            settings.answer_time_ms = answer_time_ms;
+        }
+        if let Some(finished_time_ms) = update.finished_time_ms {
+            settings.finished_time_ms = finished_time_ms;
+        }
+        if let Some(scoring) = update.scoring {
+            settings.scoring = match scoring.as_str() {
+                "classic" => ScoringMode::Classic,
+                "time_decay" => ScoringMode::TimeDecay {
+                    max_points: 1000,
+                    min_points: 500,
+                },
+                "streak_bonus" => ScoringMode::StreakBonus { bonus: 1 },
+                _ => {
+                    return Err(
+                        json!({"success": false, "error": "Unknown scoring mode"}).to_string()
+                    );
+                }
+            };
+        }
+        if let Some(team_mode) = update.team_mode {
+            settings.team_mode = match team_mode.as_str() {
+                "individual" => TeamMode::Individual,
+                "captain" => TeamMode::Captain,
+                "majority_vote" => TeamMode::MajorityVote,
+                _ => {
+                    return Err(json!({"success": false, "error": "Unknown team mode"}).to_string());
+                }
+            };
+        }
+        if let Some(repeat_after_days) = update.repeat_after_days {
+            settings.repeat_after_days = repeat_after_days;
+        }
+        if let Some(question_limit) = update.question_limit {
+            settings.question_limit = question_limit;
+        }
+        if let Some(categories) = update.categories {
+            settings.categories = parse_category_list(&categories);
+        }
+        if let Some(excluded_categories) = update.excluded_categories {
+            settings.excluded_categories = parse_category_list(&excluded_categories);
+        }
+        if let Some(packs) = update.packs {
+            settings.packs = parse_category_list(&packs);
+            if let Some(unknown) = settings
+                .packs
+                .iter()
+                .find(|name| !all_questions.packs.iter().any(|pack| pack.name == **name))
+            {
+                return Err(json!({
+                    "success": false,
+                    "error": format!("Unknown question pack {}", unknown),
+                })
+                .to_string());
             }
-            "newgame" => {
-                game_state_mutex.newgame_flag = true;
-                return Ok(command);
+        }
+        if let Some(selection) = update.selection {
+            settings.selection = match selection.as_str() {
+                "random" => SelectionMode::Random,
+                "round_robin" => SelectionMode::RoundRobin,
+                "themed_rounds" => SelectionMode::ThemedRounds {
+                    round_length: update.round_length.unwrap_or(3),
+                },
+                _ => {
+                    return Err(
+                        json!({"success": false, "error": "Unknown selection mode"}).to_string()
+                    );
+                }
+            };
+        }
+        if let Err(error) = settings.validate() {
+            return Err(json!({"success": false, "error": error}).to_string());
+        }
+
+        let available =
+            count_available_questions(all_questions, &questions_mutex, &history_mutex, &settings);
+        if settings.question_limit > available {
+            return Err(json!({
+                "success": false,
+                "error": format!(
+                    "Only {} questions available which have not been asked within {} days",
+                    available, settings.repeat_after_days
+                ),
+                "available_questions": available,
+            })
+            .to_string());
+        }
+
+        game_state_mutex.settings = settings;
+        game_state_mutex.error = None;
+        Ok(json!({
+            "success": true,
+            "settings": game_state_mutex.settings,
+            "available_questions": available,
+        })
+        .to_string())
+    }
+
+    /// Presenter accepts or rejects a borderline typed answer
+    fn review_answer(
+        game_state: &Arc<Mutex<GameState>>,
+        answer: &str,
+        accept: bool,
+    ) -> Result<String, String> {
+        let mut game_state_mutex = match game_state.lock() {
+            Ok(mutex) => mutex,
+            Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+        };
+
+        if game_state_mutex.game_stage != GameStage::GameInProgress
+            || game_state_mutex.question_stage != QuestionStage::QuestionReview
+        {
+            return Err(
+                json!({"success": false, "error": "No answers to review at this time"}).to_string(),
+            );
+        }
+
+        let answer = normalize_text(answer);
+        match game_state_mutex
+            .review
+            .iter_mut()
+            .find(|review| review.answer == answer)
+        {
+            Some(review) => {
+                review.accepted = Some(accept);
+                Ok(json!({"success": true}).to_string())
             }
+            None => Err(
+                json!({"success": false, "error": "Answer is not waiting for review"}).to_string(),
+            ),
+        }
+    }
+
+    fn handle_presenter_command(room: &Room, command: String) -> Result<String, String> {
+        let event = match command.as_str() {
+            "proceed" => GameEvent::Proceed,
+            "newgame" => GameEvent::NewGame,
             _ => {
                 return Err("Unknown command".to_string());
             }
+        };
+
+        match room.events_tx.send(event) {
+            Ok(_) => Ok(command),
+            Err(_) => Err("Game controller is not running".to_string()),
         }
     }
 }
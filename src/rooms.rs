@@ -0,0 +1,282 @@
+pub mod registry {
+    use crate::{
+        external_apis::host::Host,
+        game::{
+            controller::run_game_controller,
+            events::GameEvent,
+            settings::GameSettings,
+            state::{Answers, GameState, Questions},
+        },
+        helpers::{
+            secrets::{generate_secret, secrets_match},
+            time_helpers::uptime_ms,
+        },
+        questions::{bank::BankHandle, history::QuestionHistory},
+    };
+    use rand::seq::IndexedRandom;
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            mpsc::{self, Sender},
+            Arc, Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+    use uuid::Uuid;
+
+    /// Letters which are hard to confuse with each other when read off a TV screen
+    const JOIN_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+    const JOIN_CODE_LENGTH: usize = 4;
+    /// Room nobody has used for this long is closed, a quiz night fits well within it
+    const ROOM_IDLE_TIMEOUT_MS: u64 = 3 * 60 * 60 * 1000;
+    const ROOM_REAPER_INTERVAL: Duration = Duration::from_secs(60);
+    /// Every room has its own controller thread, so anyone may only open this many. More
+    /// need the admin token.
+    pub const MAX_OPEN_ROOMS: usize = 20;
+
+    /// One quiz with its own players, questions, answers and game controller
+    pub struct Room {
+        pub code: String,
+        /// Given to the presenter who created the room, needed to control the game
+        presenter_secret: String,
+        /// Uptime of the latest request or state change, see `run_room_reaper`
+        last_activity: AtomicU64,
+        pub game_state: Arc<Mutex<GameState>>,
+        pub questions: Arc<Mutex<Vec<Questions>>>,
+        pub answers: Arc<Mutex<Vec<Answers>>>,
+        pub events_tx: Sender<GameEvent>,
+    }
+
+    impl Room {
+        pub fn presenter_secret(&self) -> &str {
+            &self.presenter_secret
+        }
+
+        pub fn is_presenter(&self, secret: &str) -> bool {
+            secrets_match(&self.presenter_secret, secret)
+        }
+
+        fn touch(&self) {
+            self.last_activity.store(uptime_ms(), Ordering::Relaxed);
+        }
+
+        fn idle_for(&self, now: u64) -> u64 {
+            now.saturating_sub(self.last_activity.load(Ordering::Relaxed))
+        }
+    }
+
+    /// All open rooms keyed by join code. Question bank, host and history are shared by
+    /// every room.
+    pub struct RoomRegistry {
+        rooms: Mutex<HashMap<String, Arc<Room>>>,
+        pub question_bank: Arc<BankHandle>,
+        host: Host,
+        pub history: Arc<Mutex<QuestionHistory>>,
+        settings: GameSettings,
+        state_changed_tx: Sender<String>,
+    }
+
+    impl RoomRegistry {
+        pub fn new(
+            question_bank: Arc<BankHandle>,
+            host: Host,
+            history: Arc<Mutex<QuestionHistory>>,
+            settings: GameSettings,
+            state_changed_tx: Sender<String>,
+        ) -> RoomRegistry {
+            RoomRegistry {
+                rooms: Mutex::new(HashMap::new()),
+                question_bank,
+                host,
+                history,
+                settings,
+                state_changed_tx,
+            }
+        }
+
+        /// Open a new room with default settings and start its game controller. Only
+        /// `MAX_OPEN_ROOMS` may be open at once unless the caller is `privileged`.
+        pub fn create_room(&self, privileged: bool) -> Result<Arc<Room>, String> {
+            let mut rooms_mutex = match self.rooms.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+            if !privileged && rooms_mutex.len() >= MAX_OPEN_ROOMS {
+                return Err(format!(
+                    "Too many rooms open ({}), close one or try again later",
+                    rooms_mutex.len()
+                ));
+            }
+
+            let mut code = generate_join_code();
+            while rooms_mutex.contains_key(&code) {
+                code = generate_join_code();
+            }
+
+            let (events_tx, events_rx) = mpsc::channel::<GameEvent>();
+            let room = Arc::new(Room {
+                code: code.clone(),
+                presenter_secret: generate_secret(),
+                last_activity: AtomicU64::new(uptime_ms()),
+                game_state: Arc::new(Mutex::new(GameState {
+                    settings: self.settings.clone(),
+                    ..Default::default()
+                })),
+                questions: Arc::new(Mutex::new(vec![])),
+                answers: Arc::new(Mutex::new(vec![])),
+                events_tx,
+            });
+
+            let room_clone = room.clone();
+            let question_bank = self.question_bank.clone();
+            let host = self.host.clone();
+            let history = self.history.clone();
+            let state_changed_tx = self.state_changed_tx.clone();
+
+            // Start game controller
+            let builder = thread::Builder::new().name(format!("Game controller {}", code));
+            match builder.spawn(move || {
+                run_game_controller(
+                    room_clone,
+                    question_bank,
+                    host,
+                    history,
+                    events_rx,
+                    state_changed_tx,
+                );
+            }) {
+                Ok(_) => (),
+                Err(error) => {
+                    return Err(format!("Unable to start game controller: {}", error));
+                }
+            }
+
+            println!("Room {} created", code);
+            rooms_mutex.insert(code, room.clone());
+            Ok(room)
+        }
+
+        /// Join codes are case insensitive. Every lookup counts as activity in the room.
+        pub fn get_room(&self, code: &str) -> Option<Arc<Room>> {
+            let rooms_mutex = match self.rooms.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+
+            let room = rooms_mutex.get(&code.to_uppercase()).cloned();
+            if let Some(room) = room.as_ref() {
+                room.touch();
+            }
+            room
+        }
+
+        /// State changes count as activity, so a game keeps its room open even if
+        /// clients only listen to pushed state
+        pub fn touch_room(&self, code: &str) {
+            let _ = self.get_room(code);
+        }
+
+        /// Room for the old single game endpoints without a join code. Only works while
+        /// there is exactly one room, otherwise it would be a guess.
+        pub fn get_only_room(&self) -> Option<Arc<Room>> {
+            let rooms_mutex = match self.rooms.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+
+            if rooms_mutex.len() != 1 {
+                return None;
+            }
+            let room = rooms_mutex.values().next().cloned();
+            if let Some(room) = room.as_ref() {
+                room.touch();
+            }
+            room
+        }
+
+        /// Room in which the player has registered
+        pub fn find_player_room(&self, uuid: Uuid) -> Option<Arc<Room>> {
+            let rooms: Vec<Arc<Room>> = {
+                let rooms_mutex = match self.rooms.lock() {
+                    Ok(mutex) => mutex,
+                    Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+                };
+                rooms_mutex.values().cloned().collect()
+            };
+
+            let room = rooms.into_iter().find(|room| {
+                let game_state_mutex = match room.game_state.lock() {
+                    Ok(mutex) => mutex,
+                    Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+                };
+                game_state_mutex
+                    .players
+                    .iter()
+                    .any(|player| player.uuid == uuid)
+            });
+            if let Some(room) = room.as_ref() {
+                room.touch();
+            }
+            room
+        }
+
+        /// Remove room and stop its game controller
+        pub fn close_room(&self, code: &str) -> Result<(), String> {
+            let mut rooms_mutex = match self.rooms.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+
+            match rooms_mutex.remove(&code.to_uppercase()) {
+                Some(room) => {
+                    println!("Room {} closed", room.code);
+                    self.stop_room(&room);
+                    Ok(())
+                }
+                None => Err("Room not found".to_string()),
+            }
+        }
+
+        /// Close rooms which have not been used for `ROOM_IDLE_TIMEOUT_MS`, e.g. when
+        /// the presenter just closed the browser
+        pub fn close_idle_rooms(&self, now: u64) {
+            let mut rooms_mutex = match self.rooms.lock() {
+                Ok(mutex) => mutex,
+                Err(poisoned_mutex) => poisoned_mutex.into_inner(),
+            };
+
+            rooms_mutex.retain(|_, room| {
+                if room.idle_for(now) < ROOM_IDLE_TIMEOUT_MS {
+                    return true;
+                }
+                println!("Room {} closed after being idle", room.code);
+                self.stop_room(room);
+                false
+            });
+        }
+
+        /// Stop game controller and have clients' sockets dropped
+        fn stop_room(&self, room: &Room) {
+            let _ = room.events_tx.send(GameEvent::CloseRoom);
+            let _ = self.state_changed_tx.send(room.code.clone());
+        }
+    }
+
+    /// Close idle rooms every now and then, does not return
+    pub fn run_room_reaper(rooms: Arc<RoomRegistry>) {
+        loop {
+            thread::sleep(ROOM_REAPER_INTERVAL);
+            rooms.close_idle_rooms(uptime_ms());
+        }
+    }
+
+    fn generate_join_code() -> String {
+        let mut rng = rand::rng();
+        (0..JOIN_CODE_LENGTH)
+            .filter_map(|_| JOIN_CODE_ALPHABET.choose(&mut rng))
+            .map(|letter| *letter as char)
+            .collect()
+    }
+}
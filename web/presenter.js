@@ -3,43 +3,270 @@ $.ajaxSetup({
 	async: false
 });
 
+var room_code = "";
+var room_secret = "";
 var presenter_state = {};
 var presenter_tick = "";
+var presenter_socket = null;
 var narrator = "";
 var audio_playing = false;
 var last_audio_played = "";
+var stage_deadline = null;
+var media_played = "";
+var auto_proceed = null;
+var auto_proceed_stage = "";
 
 function get_presenter_state(uuid)
 {
-	$.getJSON("/get_presenter_state/", function(data) {
+	$.getJSON("/room/" + room_code + "/get_presenter_state", function(data) {
 		presenter_state = data;
         process_presenter_state();
 	});
 }
 
+function update_countdown()
+{
+    if (stage_deadline == null) {
+        $("span#countdown").html("");
+        return;
+    }
+    var remaining = Math.max(0, Math.ceil((stage_deadline - Date.now()) / 1000));
+    $("span#countdown").html(remaining + " s");
+}
+
+function set_stage_deadline(state)
+{
+    if (state["stage_time_remaining"] == null) {
+        stage_deadline = null;
+    } else {
+        stage_deadline = Date.now() + state["stage_time_remaining"];
+    }
+}
+
+function open_room()
+{
+    /* Reuse room from address so that reloading presenter does not lose the game.
+       Secret is kept in this tab only, the address is visible on the TV. */
+    if (window.location.hash.length > 1) {
+        room_code = window.location.hash.substring(1);
+        room_secret = sessionStorage.getItem("room-secret-" + room_code) || "";
+        if (room_secret == "") {
+            room_code = "";
+        } else {
+            $.getJSON("/room/" + room_code + "/settings").fail(function() {
+                room_code = "";
+            });
+        }
+    }
+    if (room_code == "") {
+        $.post("/room/create", {}, function(data) {
+            if (data.success == true) {
+                room_code = data["code"];
+                room_secret = data["secret"];
+                sessionStorage.setItem("room-secret-" + room_code, room_secret);
+                window.location.hash = room_code;
+            } else {
+                alert("Peliä ei voitu luoda!\n\n" + data["error"]);
+            }
+        });
+    }
+    $("span#room-code").html(room_code);
+    $("span#join-address").html(window.location.protocol + "//" + window.location.host + "/#" + room_code);
+}
+
+function get_settings()
+{
+    $.getJSON("/room/" + room_code + "/settings", function(data) {
+        if (data.success == true) {
+            $("input#introduction-time").val(data["settings"]["introduction_time_ms"] / 1000);
+            $("input#answer-time").val(data["settings"]["answer_time_ms"] / 1000);
+            $("input#finished-time").val(data["settings"]["finished_time_ms"] / 1000);
+            $("input#question-limit").val(data["settings"]["question_limit"]);
+            $("input#categories").val(data["settings"]["categories"].join(", "));
+            $("span#available-questions").html(data["available_questions"]);
+            $("input#excluded-categories").val(data["settings"]["excluded_categories"].join(", "));
+            $("select#selection").val(data["settings"]["selection"]["mode"]);
+            $("input#round-length").val(data["settings"]["selection"]["round_length"] || 3);
+            $("input#repeat-after-days").val(data["settings"]["repeat_after_days"]);
+            $("select#scoring").val(data["settings"]["scoring"]["mode"]);
+            $("select#team-mode").val(data["settings"]["team_mode"]);
+            get_packs(data["settings"]["packs"]);
+        }
+    });
+    get_categories();
+}
+
+function get_packs(selected)
+{
+    $.getJSON("/room/" + room_code + "/packs", function(data) {
+        if (data.success == true) {
+            var list = [];
+            for (var i = 0; i < data["packs"].length; i++) {
+                var pack = data["packs"][i];
+                var metadata = pack["metadata"];
+                var checked = (selected.length == 0 || selected.indexOf(pack["name"]) >= 0) ? " checked" : "";
+                var details = [metadata["author"], metadata["language"], metadata["difficulty"]].filter(function(detail) {
+                    return detail != null && detail != "";
+                });
+                list.push("<label><input type=\"checkbox\" class=\"pack\" value=\"" + pack["name"] + "\"" + checked + " /> " +
+                    (metadata["title"] || pack["name"]) + " (" + details.join(", ") + ") " +
+                    pack["available"] + "/" + pack["total"] + "</label>");
+            }
+            $("div#pack-list").html(list.join("<br />"));
+        }
+    });
+}
+
+/* All packs checked is sent as empty list so that packs added later are included too */
+function selected_packs()
+{
+    var packs = $("input.pack:checked").map(function() {
+        return $(this).val();
+    }).get();
+    if (packs.length == $("input.pack").length) {
+        return "";
+    }
+    return packs.join(",");
+}
+
+function get_categories()
+{
+    $.getJSON("/room/" + room_code + "/categories", function(data) {
+        if (data.success == true) {
+            var list = [];
+            for (var i = 0; i < data["categories"].length; i++) {
+                var category = data["categories"][i];
+                list.push(category["category"] + " (" + category["available"] + "/" + category["total"] + ")");
+            }
+            $("span#category-list").html(list.join(", "));
+        }
+    });
+}
+
+function save_settings()
+{
+    $.post("/room/" + room_code + "/settings", {
+        secret: room_secret,
+        introduction_time_ms: Math.round($("input#introduction-time").val() * 1000),
+        answer_time_ms: Math.round($("input#answer-time").val() * 1000),
+        finished_time_ms: Math.round($("input#finished-time").val() * 1000),
+        question_limit: $("input#question-limit").val(),
+        categories: $("input#categories").val(),
+        excluded_categories: $("input#excluded-categories").val(),
+        packs: selected_packs(),
+        selection: $("select#selection").val(),
+        round_length: $("input#round-length").val(),
+        repeat_after_days: $("input#repeat-after-days").val(),
+        scoring: $("select#scoring").val(),
+        team_mode: $("select#team-mode").val()
+    }, function(data) {
+        if (data.success != true) {
+            alert("Asetuksia ei voitu tallentaa!\n\n" + data["error"]);
+        }
+        get_settings();
+    });
+}
+
+function start_presenter_updates()
+{
+    /* Prefer pushed state, fall back to polling if WebSocket fails */
+    if (!("WebSocket" in window)) {
+        presenter_tick = setInterval(function() { get_presenter_state(); }, 1000);
+        return;
+    }
+    var protocol = (window.location.protocol == "https:") ? "wss://" : "ws://";
+    presenter_socket = new WebSocket(protocol + window.location.host + "/room/" + room_code + "/ws/presenter");
+    presenter_socket.onmessage = function(event) {
+        presenter_state = JSON.parse(event.data);
+        process_presenter_state();
+    };
+    presenter_socket.onclose = function() {
+        console.log("State socket closed, polling instead");
+        presenter_socket = null;
+        if (presenter_tick == "") {
+            presenter_tick = setInterval(function() { get_presenter_state(); }, 1000);
+        }
+    };
+}
+
 function command_to_game(command)
 {
     console.log("Sending command to game: " + command);
-    $.post("/command", { command: command }, function(data) {
+    $.post("/room/" + room_code + "/command", { secret: room_secret, command: command }, function(data) {
         console.log("Response to command (" + command + ") is: " + data);
     });
 }
 
+/* Same state is pushed several times per stage, e.g. while audio is prepared */
+function stage_of(state)
+{
+    return state["game_stage"] + "/" + state["question_stage"] + "/" + state["question_number"];
+}
+
+/* Proceed once per stage, otherwise repeated pushes would skip stages */
+function proceed_after(state, delay)
+{
+    if (auto_proceed_stage == stage_of(state)) {
+        return;
+    }
+    cancel_auto_proceed();
+    auto_proceed_stage = stage_of(state);
+    auto_proceed = setTimeout(function() {
+        auto_proceed = null;
+        command_to_game("proceed");
+    }, delay);
+}
+
+function cancel_auto_proceed()
+{
+    if (auto_proceed != null) {
+        clearTimeout(auto_proceed);
+        auto_proceed = null;
+    }
+    auto_proceed_stage = "";
+}
+
+function narrate(state, delay)
+{
+    /* Audio is generated in the background, wait for it or move on by itself without */
+    if (state["generating"] == true) {
+        return;
+    }
+    if (state["audio"] == null) {
+        proceed_after(state, delay);
+    } else {
+        play_audio(state["audio"]);
+    }
+}
+
 function process_presenter_state() {
+    set_stage_deadline(presenter_state);
+    if (auto_proceed_stage != "" && auto_proceed_stage != stage_of(presenter_state)) {
+        cancel_auto_proceed();
+    }
+    $("div#generating").toggle(presenter_state["generating"] == true);
     if (presenter_state["game_stage"] == "WaitingForPlayers")
     {
         $("div#results").hide();
         $("span#count-players").html(presenter_state["num_players"]);
+        if (presenter_state["error"] == null) {
+            $("div#game-error").hide();
+        } else {
+            $("div#game-error").html(presenter_state["error"]).show();
+        }
     }
     else if (presenter_state["game_stage"] == "IntroducePlayers")
     {
-        if (presenter_state["audio"] == null) {
-            command_to_game("proceed");
+        if (presenter_state["generating"] != true && presenter_state["audio"] == null
+            && presenter_state["tts_text"] == null) {
+            proceed_after(presenter_state, 0);
         } else {
-            $("h2#player-intro").html(presenter_state["tts_text"]);
+            $("h2#player-intro").text(presenter_state["tts_text"]);
+            $("span#audio-prepared").html(presenter_state["audio_prepared"]);
+            $("span#audio-to-prepare").html(presenter_state["audio_to_prepare"]);
             $("div#waiting-for-players-presenter").hide();
             $("div#introduce-players").show();
-            play_audio(presenter_state["audio"]);
+            narrate(presenter_state, 8000);
         }
     }
     else if (presenter_state["game_stage"] == "GameInProgress")
@@ -49,34 +276,35 @@ function process_presenter_state() {
             $("div#introduce-players").hide();
             $("div#question-answer").hide();
             $("h3#question").html(presenter_state["question"]);
+            show_media(presenter_state["media"]);
             $("div#question").show();
-            if (presenter_state["audio"] == null) {
-                setTimeout(function() {
-                    command_to_game("proceed");
-                }, 5000);
-            } else {
-                play_audio(presenter_state["audio"]);
-            }
+            narrate(presenter_state, 5000);
         }
         else if (presenter_state["question_stage"] == "QuestionAnswerTime")
         {
             $("h4#answer-count").show();
             $("span#answer-count").html(presenter_state["num_players_answered"]);
+            play_media(presenter_state["media"]);
+        }
+        else if (presenter_state["question_stage"] == "QuestionReview")
+        {
+            stop_media();
+            $("div#question").hide();
+            show_review(presenter_state["review"]);
+            $("div#review").show();
         }
         else if (presenter_state["question_stage"] == "QuestionFinished")
         {
+            stop_media();
+            $("div#review").hide();
             $("div#question").hide();
             $("h3#answer").html(presenter_state["answer"]);
             $("h3#context").html(presenter_state["context"]);
+            show_reveal_stats(presenter_state["reveal"]);
+            show_leaderboard("table#leaderboard", presenter_state["leaderboard"]);
             $("h4#answer-count").hide();
             $("div#question-answer").show();
-            if (presenter_state["audio"] == null) {
-                setTimeout(function() {
-                    command_to_game("proceed");
-                }, 5000);
-            } else {
-                play_audio(presenter_state["audio"]);
-            }
+            narrate(presenter_state, 5000);
         }
     }
     else if (presenter_state["game_stage"] == "ResultsShow")
@@ -84,16 +312,134 @@ function process_presenter_state() {
         $("div#question").hide();
         $("div#introduce-players").hide();
         $("div#question-answer").hide();
-        $("h3#scores").html(presenter_state["scores"]);
+        show_leaderboard("table#scores", presenter_state["scores"]);
         $("div#results").show();
-        if (presenter_state["audio"] == null) {
-            setTimeout(function() {
-                command_to_game("proceed");
-            }, 5000);
-        } else {
-            play_audio(presenter_state["audio"]);
+        narrate(presenter_state, 5000);
+    }
+}
+
+function show_reveal_stats(reveal)
+{
+    if (reveal == null) {
+        return;
+    }
+    /* Answers and names are typed by players, never insert them as HTML */
+    var list = $("ul#answer-counts").empty();
+    for (var i = 0; i < reveal["answer_counts"].length; i++) {
+        var count = reveal["answer_counts"][i];
+        $("<li></li>").text(count["answer"] + ": " + count["count"]).appendTo(list);
+    }
+    if (reveal["correct_players"].length > 0) {
+        $("span#correct-players").text(reveal["correct_players"].join(", "));
+    } else {
+        $("span#correct-players").text("ei kukaan");
+    }
+    if (reveal["fastest_player"] != null) {
+        $("span#fastest-player").text(reveal["fastest_player"] + " (" + (reveal["fastest_time_ms"] / 1000).toFixed(1) + " s)");
+        $("h4#fastest-player").show();
+    } else {
+        $("h4#fastest-player").hide();
+    }
+}
+
+function show_review(review)
+{
+    var table = $("table#review-answers").empty();
+    for (var i = 0; i < review.length; i++) {
+        var row = $("<tr></tr>");
+        $("<td></td>").text(review[i]["answer"]).appendTo(row);
+        $("<td></td>").text("(oikea: " + review[i]["closest_accepted"] + ")").appendTo(row);
+        $("<td></td>").text(review[i]["players"].join(", ")).appendTo(row);
+        var buttons = $("<td></td>");
+        $("<button type=\"button\" class=\"btn btn-sm btn-review\" data-decision=\"accept\">Hyväksy</button>")
+            .attr("data-answer", review[i]["answer"])
+            .addClass(review[i]["accepted"] == true ? "btn-success" : "btn-outline-success")
+            .appendTo(buttons);
+        $("<button type=\"button\" class=\"btn btn-sm btn-review\" data-decision=\"reject\">Hylkää</button>")
+            .attr("data-answer", review[i]["answer"])
+            .addClass(review[i]["accepted"] == false ? "btn-danger" : "btn-outline-danger")
+            .appendTo(buttons);
+        buttons.appendTo(row);
+        row.appendTo(table);
+    }
+}
+
+function review_answer(answer, decision)
+{
+    $.post("/room/" + room_code + "/review", { secret: room_secret, answer: answer, decision: decision }, function(data) {
+        if (data.success != true) {
+            alert("Vastausta ei voitu tarkistaa!\n\n" + data["error"]);
         }
+    });
+}
+
+function show_leaderboard(selector, leaderboard)
+{
+    if (leaderboard == null) {
+        return;
+    }
+    /* Team and player names are typed by players, never insert them as HTML */
+    var table = $(selector).empty();
+    for (var i = 0; i < leaderboard.length; i++) {
+        var standing = leaderboard[i];
+        var change = "";
+        if (standing["position_change"] > 0) {
+            change = "\u25B2 " + standing["position_change"];
+        } else if (standing["position_change"] < 0) {
+            change = "\u25BC " + (-standing["position_change"]);
+        }
+        var name = standing["name"];
+        if (standing["members"].length > 0) {
+            name += " (" + standing["members"].join(", ") + ")";
+        }
+        var row = $("<tr></tr>");
+        $("<td></td>").text(standing["position"] + ".").appendTo(row);
+        $("<td></td>").text(name).appendTo(row);
+        $("<td></td>").text(standing["points"]).appendTo(row);
+        $("<td></td>").text(change).appendTo(row);
+        row.appendTo(table);
+    }
+}
+
+/* Image is shown with the question, clips are played only once answering starts
+   so that they don't overlap with the narrator */
+function show_media(media) {
+    stop_media();
+    if (media == null) {
+        return;
+    }
+    if (media["image"] != null) {
+        $("img#question-image").attr("src", media["image"]).show();
+    }
+    if (media["video"] != null) {
+        $("video#question-video").attr("src", media["video"]).show();
+    }
+}
+
+function play_media(media) {
+    if (media == null) {
+        return;
     }
+    var clip = media["video"] != null ? "video#question-video" : "audio#question-clip";
+    var path = media["video"] != null ? media["video"] : media["audio"];
+    if (path == null || path == media_played) {
+        return;
+    }
+    media_played = path;
+    console.log("Play media: " + path);
+    if (media["video"] == null) {
+        $(clip).attr("src", path);
+    }
+    $(clip)[0].play();
+}
+
+function stop_media() {
+    $("audio#question-clip, video#question-video").each(function() {
+        this.pause();
+    });
+    $("img#question-image, video#question-video").hide();
+    $("img#question-image").attr("src", "");
+    media_played = "";
 }
 
 function play_audio(path) {
@@ -102,7 +448,9 @@ function play_audio(path) {
             last_audio_played = path;
             audio_playing = true;
             console.log("Play audio: " + path);
-            $("source#path").attr("src", path);
+            // Offline speech engines produce WAV instead of MP3
+            $("source#path").attr("src", path)
+                .attr("type", path.endsWith(".wav") ? "audio/wav" : "audio/mpeg");
             narrator.load();
             narrator.play();
         }
@@ -114,6 +462,14 @@ $(document).ready(function() {
     narrator.addEventListener("ended", function() {
         $("#audio-finished").trigger("click");
         audio_playing = false;
+        /* State may have moved on to new audio while previous was playing */
+        if (presenter_state["audio"] != null && presenter_state["audio"] != last_audio_played) {
+            process_presenter_state();
+        }
+    });
+
+    $(document).on("click", "#btn-save-settings", function() {
+        save_settings();
     });
 
     $(document).on("click", "#btn-start-game", function() {
@@ -123,9 +479,23 @@ $(document).ready(function() {
     $(document).on("click", "#audio-finished", function() {
         console.log("Audio finished playing");
         setTimeout(function() {
-            command_to_game("proceed");
+            /* Review is only finished by presenter */
+            if (presenter_state["question_stage"] != "QuestionReview") {
+                command_to_game("proceed");
+            }
         }, 3000);
     });
 
-    presenter_tick = setInterval(function() { get_presenter_state(); }, 1000);
+    $(document).on("click", ".btn-review", function() {
+        review_answer($(this).attr("data-answer"), $(this).attr("data-decision"));
+    });
+
+    $(document).on("click", "#btn-finish-review", function() {
+        command_to_game("proceed");
+    });
+
+    open_room();
+    get_settings();
+    setInterval(update_countdown, 250);
+    start_presenter_updates();
 });
\ No newline at end of file
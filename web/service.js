@@ -4,49 +4,109 @@ $.ajaxSetup({
 });
 
 var uuid = "";
+var room_code = "";
 var game_state = {};
 var game_tick = "";
+var game_socket = null;
 var override_game = false;
+var stage_deadline = null;
+var rendered_question = 0;
 
 function get_player_state(uuid)
 {
-	$.getJSON("/get_player_state/" + uuid, function(data) {
+	$.getJSON("/room/" + room_code + "/get_player_state/" + uuid, function(data) {
 		game_state = data;
         process_player_state();
 	});
 }
 
+function update_countdown()
+{
+    if (stage_deadline == null) {
+        $("span#countdown").html("");
+        return;
+    }
+    var remaining = Math.max(0, Math.ceil((stage_deadline - Date.now()) / 1000));
+    $("span#countdown").html(remaining + " s");
+}
+
+function set_stage_deadline(state)
+{
+    if (state["stage_time_remaining"] == null) {
+        stage_deadline = null;
+    } else {
+        stage_deadline = Date.now() + state["stage_time_remaining"];
+    }
+}
+
+function start_player_updates(uuid)
+{
+    /* Prefer pushed state, fall back to polling if WebSocket fails */
+    if (!("WebSocket" in window)) {
+        game_tick = setInterval(function() { get_player_state(uuid); }, 1000);
+        return;
+    }
+    var protocol = (window.location.protocol == "https:") ? "wss://" : "ws://";
+    game_socket = new WebSocket(protocol + window.location.host + "/room/" + room_code + "/ws/player/" + uuid);
+    game_socket.onmessage = function(event) {
+        game_state = JSON.parse(event.data);
+        process_player_state();
+    };
+    game_socket.onclose = function() {
+        console.log("State socket closed, polling instead");
+        game_socket = null;
+        if (game_tick == "") {
+            game_tick = setInterval(function() { get_player_state(uuid); }, 1000);
+        }
+    };
+}
+
 function submit_answer(uuid, answer)
 {
-    $.post("/submit_answer", { uuid: uuid, answer: answer }, function(data) {
+    $.post("/room/" + room_code + "/submit_answer", { uuid: uuid, answer: answer }, function(data) {
         console.log(data);
     });
 }
 
-function register_player(name)
+function register_player(code, name, team)
 {
-    $.post("/register_player", { name: name }, function(data) {
+    room_code = code.trim().toUpperCase();
+    $.post("/room/" + room_code + "/register_player", { name: name, team: team }, function(data) {
         if (data.success == true) {
             uuid = data["uuid"];
             console.log("uuid: " + uuid);
             $("div#join-game").fadeOut("slow", function() {
                 $("div#waiting-for-players").fadeIn("slow");
-                game_tick = setInterval(function() { get_player_state(uuid); }, 1000);
+                start_player_updates(uuid);
             });
         } else {
             alert("Ei voitu liittyä peliin!\n\n" + data["error"]);
         }
+    }).fail(function() {
+        alert("Ei voitu liittyä peliin!\n\nTarkista pelin koodi.");
     });
 }
 
 function process_player_state() {
     //console.log(game_state);
+    set_stage_deadline(game_state);
     if (game_state["game_stage"] == "GameInProgress")
     {
         $("div#waiting-for-players").hide();
-        if (game_state["question_stage"] == "QuestionIntroduction" || game_state["question_stage"] == "QuestionFinished")
+        if (game_state["question_stage"] == "QuestionIntroduction" || game_state["question_stage"] == "QuestionReview" || game_state["question_stage"] == "QuestionFinished")
         {
             override_game = false;
+            rendered_question = 0;
+            if (game_state["correct"] == null) {
+                $("h3#answer-result").html("");
+            } else {
+                $("h3#answer-result").html((game_state["correct"] ? "Oikein!" : "Väärin.") + " Pisteesi: " + game_state["score"] + ", sijoitus: " + game_state["position"] + ".");
+            }
+            $("div#game").hide();
+            $("div#look-at-tv").show();
+        } else if (game_state["may_answer"] == false) {
+            /* Captain answers for the whole team */
+            $("h3#answer-result").text("Joukkueen " + game_state["team"] + " kapteeni vastaa.");
             $("div#game").hide();
             $("div#look-at-tv").show();
         } else {
@@ -54,53 +114,91 @@ function process_player_state() {
             {
                 $("div#look-at-tv").hide();
                 $("div#game").show();
-                $("button#btn-answer-one").html(game_state["answer_options"][0]);
-                $("button#btn-answer-two").html(game_state["answer_options"][1]);
-                $("button#btn-answer-three").html(game_state["answer_options"][2]);
-                $("button#btn-answer-four").html(game_state["answer_options"][3]);
+                if (rendered_question != game_state["question_number"]) {
+                    rendered_question = game_state["question_number"];
+                    render_answer_controls(game_state);
+                }
             }
         }
     }
     else if (game_state["game_stage"] == "ResultsShow")
     {
         console.log("results show");
+        $("div#game").hide();
+        $("div#look-at-tv").show();
+        $("h3#answer-result").html("Peli päättyi! Pisteesi: " + game_state["score"] + ", sijoitus: " + game_state["position"] + "/" + game_state["num_ranked"] + ".");
     }
 }
 
+/* Show buttons, input field or ordering list depending on question kind */
+function render_answer_controls(state)
+{
+    $("div#answer-choice").hide();
+    $("div#answer-input").hide();
+    $("div#answer-ordering").hide();
+    if (state["answer_kind"] == "choice") {
+        var choice = $("div#answer-choice").empty();
+        for (var i = 0; i < state["answer_options"].length; i++) {
+            $("<button class=\"btn btn-secondary btn-lg btn-block btn-answer\" type=\"button\"></button>")
+                .text(state["answer_options"][i]).appendTo(choice);
+        }
+        choice.show();
+    } else if (state["answer_kind"] == "ordering") {
+        var list = $("ol#ordering-items").empty();
+        for (var i = 0; i < state["answer_options"].length; i++) {
+            var item = $("<li></li>");
+            $("<span class=\"ordering-item\"></span>").text(state["answer_options"][i]).appendTo(item);
+            $("<button class=\"btn btn-light btn-sm btn-move-up\" type=\"button\">&#9650;</button>").appendTo(item);
+            item.appendTo(list);
+        }
+        $("div#answer-ordering").show();
+    } else {
+        $("input#typed-answer").val("");
+        $("input#typed-answer").attr("inputmode", state["answer_kind"] == "numeric" ? "decimal" : "text");
+        $("div#answer-input").show();
+    }
+}
+
+function answer_and_look_at_tv(answer)
+{
+    submit_answer(uuid, answer);
+    override_game = true;
+    $("div#game").fadeOut("slow", function() {
+        $("div#look-at-tv").fadeIn("slow");
+    });
+}
+
 $(document).ready(function() {
+    setInterval(update_countdown, 250);
+
     $(document).on("click", "#btn-register-player", function() {
-        register_player($("input#player-name").val());
+        register_player($("input#room-code").val(), $("input#player-name").val(), $("input#team-name").val());
     });
 
-    $(document).on("click", "#btn-answer-one", function() {
-        submit_answer(uuid, $(this).html());
-        override_game = true;
-        $("div#game").fadeOut("slow", function() {
-            $("div#look-at-tv").fadeIn("slow");
-        });
+    /* Join code can be given in link, e.g. /index.html#ABCD */
+    if (window.location.hash.length > 1) {
+        $("input#room-code").val(window.location.hash.substring(1));
+    }
+
+    $(document).on("click", ".btn-answer", function() {
+        answer_and_look_at_tv($(this).text());
     });
 
-    $(document).on("click", "#btn-answer-two", function() {
-        submit_answer(uuid, $(this).html());
-        override_game = true;
-        $("div#game").fadeOut("slow", function() {
-            $("div#look-at-tv").fadeIn("slow");
-        });
+    $(document).on("click", "#btn-submit-typed", function() {
+        answer_and_look_at_tv($("input#typed-answer").val());
     });
 
-    $(document).on("click", "#btn-answer-three", function() {
-        submit_answer(uuid, $(this).html());
-        override_game = true;
-        $("div#game").fadeOut("slow", function() {
-            $("div#look-at-tv").fadeIn("slow");
-        });
+    $(document).on("click", ".btn-move-up", function() {
+        var item = $(this).closest("li");
+        item.insertBefore(item.prev());
     });
 
-    $(document).on("click", "#btn-answer-four", function() {
-        submit_answer(uuid, $(this).html());
-        override_game = true;
-        $("div#game").fadeOut("slow", function() {
-            $("div#look-at-tv").fadeIn("slow");
+    /* Ordering answer is sent one item per line */
+    $(document).on("click", "#btn-submit-ordering", function() {
+        var items = [];
+        $("ol#ordering-items span.ordering-item").each(function() {
+            items.push($(this).text());
         });
+        answer_and_look_at_tv(items.join("\n"));
     });
 });
\ No newline at end of file